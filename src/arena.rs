@@ -1,19 +1,19 @@
 use super::*;
 use bevy::ecs::Command;
 use ncollide2d::pipeline::CollisionObjectSlabHandle;
+use serde::{Deserialize, Serialize};
+
+/// Default arena size, independent of the window, can be changed with --arena WIDTHxHEIGHT.
+const ARENA_WIDTH: f32 = 2560.0;
+const ARENA_HEIGHT: f32 = 1600.0;
+/// Extra distance around the view where ghosts are shown, to cover sprites crossing the view edges.
+const GHOST_MARGIN: f32 = 200.0;
 
-#[derive(Eq, PartialEq, Copy, Clone, Debug)]
-pub enum ArenaQuadrant {
-    NE,
-    NW,
-    SW,
-    SE,
-}
 pub struct SpriteGhost {
     /// Parent entity to get the transform
     pub parent: Entity,
-    /// 0, 1 or 2 for a 3 ghost arena
-    pub id: u8,
+    /// Index in Arena.ghosts
+    pub id: usize,
 }
 
 /// Tag component for Entity with SpriteGhost children
@@ -55,74 +55,188 @@ pub trait ArenaExt {
     fn despawn_from_arena(&mut self, entity: Entity) -> &mut Self;
 }
 impl ArenaExt for Commands {
-    ///! Spawn the SpriteComponents, and 3 ghosts child with the same material
+    ///! Spawn the SpriteComponents, ghosts with the same material are added by spriteghost_count_system
     ///! Ghost 'translation' and rotation will be kept in sync,
     fn spawn_with_ghosts(&mut self, sprite_components: SpriteComponents) -> &mut Self {
-        let material = sprite_components.material.clone();
-        {
-            let mut commands = self.commands.lock();
-            commands.spawn(sprite_components);
-            let parent = commands.current_entity.unwrap();
-            let child_ids = (0..3)
-                .map(|id| {
-                    commands
-                        .spawn(SpriteComponents {
-                            material: material.clone(),
-                            draw: Draw {
-                                is_visible: false,
-                                ..Default::default()
-                            },
-                            ..Default::default()
-                        })
-                        .with(SpriteGhost { parent, id });
-                    commands.current_entity.unwrap()
-                })
-                .collect::<Vec<_>>();
-            commands.current_entity = Some(parent);
-            commands.with(SpriteGhostChildren(child_ids));
-        }
-        self
+        self.spawn(sprite_components)
+            .with(SpriteGhostChildren(Vec::new()))
     }
     fn despawn_from_arena(&mut self, entity: Entity) -> &mut Self {
         self.add_command(DespawnFromArena { entity })
     }
 }
 
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ArenaEdge {
+    /// Leaving through an edge comes back through the opposite one, as a torus.
+    Wrap,
+    /// Bodies bounce back on the edges.
+    Bounce,
+    /// Bodies bounce back on the edges, ships are damaged.
+    Walls,
+    /// Walls closing in over time.
+    Shrinking,
+}
+
+/// Speed of the Shrinking walls, in px/s.
+const ARENA_SHRINK_RATE: f32 = 10.0;
+/// Smallest size of the Shrinking walls.
+const ARENA_MIN_SIZE: f32 = 600.0;
+/// Speed factor kept when bouncing on an edge.
+const EDGE_RESTITUTION: f32 = 0.8;
+
 pub struct Arena {
     pub size: Vec2,
-    /// Quarter of the arena currently shown
-    /// Only one of NE,NW,SE,SW
-    /// Used for ghosts
-    pub shown: ArenaQuadrant,
-}
-
-fn get_ghost_translation(arena: &Arena, ghost_id: &u8) -> Vec3 {
-    match (arena.shown, ghost_id) {
-        (ArenaQuadrant::NW, 0) => Vec3::new(-arena.size.x(), 0.0, 0.0),
-        (ArenaQuadrant::NW, 1) => Vec3::new(-arena.size.x(), arena.size.y(), 0.0),
-        (ArenaQuadrant::NW, 2) => Vec3::new(0.0, arena.size.y(), 0.0),
-        (ArenaQuadrant::NE, 0) => Vec3::new(0.0, arena.size.y(), 0.0),
-        (ArenaQuadrant::NE, 1) => Vec3::new(arena.size.x(), arena.size.y(), 0.0),
-        (ArenaQuadrant::NE, 2) => Vec3::new(arena.size.x(), 0.0, 0.0),
-        (ArenaQuadrant::SE, 0) => Vec3::new(arena.size.x(), 0.0, 0.0),
-        (ArenaQuadrant::SE, 1) => Vec3::new(arena.size.x(), -arena.size.y(), 0.0),
-        (ArenaQuadrant::SE, 2) => Vec3::new(0.0, -arena.size.y(), 0.0),
-        (ArenaQuadrant::SW, 0) => Vec3::new(0.0, -arena.size.y(), 0.0),
-        (ArenaQuadrant::SW, 1) => Vec3::new(-arena.size.x(), -arena.size.y(), 0.0),
-        (ArenaQuadrant::SW, 2) => Vec3::new(-arena.size.x(), 0.0, 0.0),
-        _ => panic!("Unexpected arena.shown,ghost.id combination"),
-    }
-}
-
-pub fn spriteghost_quadrant_system(
+    pub edge: ArenaEdge,
+    /// Size inside the edges, only smaller than size when Shrinking.
+    pub bounds: Vec2,
+    /// Translation of each ghost from its parent, None for the ghosts not needed by the current view.
+    /// The count only depends on the view and arena sizes, so ghosts are not respawned while moving.
+    pub ghosts: Vec<Option<Vec2>>,
+    /// World area shown by the main camera.
+    pub view_center: Vec2,
+    pub view_size: Vec2,
+}
+
+impl Arena {
+    pub fn new(size: Vec2, edge: ArenaEdge) -> Arena {
+        Arena {
+            size,
+            edge,
+            bounds: size,
+            ghosts: Vec::new(),
+            view_center: Vec2::zero(),
+            view_size: Vec2::zero(),
+        }
+    }
+    /// Arena size from the command line, or the default one.
+    pub fn size_from_args() -> Vec2 {
+        let size = arg_value("--arena").and_then(|size| {
+            let mut dimensions = size.split('x').map(|d| d.parse::<f32>());
+            match (dimensions.next(), dimensions.next()) {
+                (Some(Ok(width)), Some(Ok(height))) if width > 0.0 && height > 0.0 => {
+                    Some(Vec2::new(width, height))
+                }
+                _ => {
+                    println!("Invalid arena size {}, expected WIDTHxHEIGHT", size);
+                    None
+                }
+            }
+        });
+        size.unwrap_or_else(|| Vec2::new(ARENA_WIDTH, ARENA_HEIGHT))
+    }
+    /// Place the ghosts, so that every copy of the arena overlapping the view is drawn.
+    pub fn update_ghosts(&mut self, view_center: Vec2, view_size: Vec2) {
+        self.view_center = view_center;
+        self.view_size = view_size;
+        if self.edge != ArenaEdge::Wrap {
+            self.ghosts.clear();
+            return;
+        }
+        let half_view = view_size / 2.0 + Vec2::splat(GHOST_MARGIN);
+        // Arena copies overlapping an interval, along one axis.
+        let copies = |center: f32, half_view: f32, size: f32| {
+            let first = ((center - half_view) / size + 0.5).floor() as i32;
+            let last = ((center + half_view) / size - 0.5).ceil() as i32;
+            (first..=last).map(move |i| i as f32 * size)
+        };
+        // At most ceil(view / size) + 1 copies overlap the view along each axis.
+        let count_x = ((2.0 * half_view.x()) / self.size.x()).ceil() as usize + 1;
+        let count_y = ((2.0 * half_view.y()) / self.size.y()).ceil() as usize + 1;
+        let mut ghosts = Vec::with_capacity(count_x * count_y - 1);
+        for x in copies(view_center.x(), half_view.x(), self.size.x()) {
+            for y in copies(view_center.y(), half_view.y(), self.size.y()) {
+                if x != 0.0 || y != 0.0 {
+                    ghosts.push(Some(Vec2::new(x, y)));
+                }
+            }
+        }
+        ghosts.resize(count_x * count_y - 1, None);
+        self.ghosts = ghosts;
+    }
+    /// Position in the window from its bottom left corner, of the closest copy of a world position.
+    pub fn world_to_screen(&self, position: Vec2, window_size: Vec2) -> Vec2 {
+        if self.view_size.x() <= 0.0 {
+            return window_size / 2.0;
+        }
+        let delta = self.wrapped_delta(self.view_center, position);
+        delta * window_size / self.view_size + window_size / 2.0
+    }
+    /// Shortest offset from one position to another, going through the edges if closer.
+    pub fn wrapped_delta(&self, from: Vec2, to: Vec2) -> Vec2 {
+        if self.edge != ArenaEdge::Wrap {
+            return to - from;
+        }
+        let wrap = |delta: f32, size: f32| delta - size * (delta / size).round();
+        Vec2::new(
+            wrap(to.x() - from.x(), self.size.x()),
+            wrap(to.y() - from.y(), self.size.y()),
+        )
+    }
+}
+
+/// Follow the main camera, to know which ghosts are needed.
+pub fn arena_view_system(
+    windows: Res<Windows>,
+    mut arena: ResMut<Arena>,
+    cameras: Query<(&Camera, &Transform)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    for (camera, transform) in cameras.iter() {
+        if camera.name == Some(String::from("Camera2d")) {
+            let scale = transform.scale.truncate();
+            let view_size = Vec2::new(window.width() as f32, window.height() as f32) * scale;
+            arena.update_ghosts(transform.translation.truncate(), view_size);
+        }
+    }
+}
+
+/// Spawn or despawn ghosts so each parent has one per Arena.ghosts entry.
+pub fn spriteghost_count_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    mut parents: Query<(Entity, Mut<SpriteGhostChildren>, &Handle<ColorMaterial>)>,
+) {
+    let count = arena.ghosts.len();
+    for (parent, mut children, material) in parents.iter_mut() {
+        while children.0.len() > count {
+            let ghost = children.0.pop().unwrap();
+            commands.despawn(ghost);
+        }
+        while children.0.len() < count {
+            commands
+                .spawn(SpriteComponents {
+                    material: material.clone(),
+                    draw: Draw {
+                        is_visible: false,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+                .with(SpriteGhost {
+                    parent,
+                    id: children.0.len(),
+                });
+            children.0.push(commands.current_entity().unwrap());
+        }
+    }
+}
+
+pub fn spriteghost_position_system(
     arena: Res<Arena>,
     mut query: Query<(&SpriteGhost, Mut<Transform>, Mut<Draw>)>,
     query_transform: Query<Without<SpriteGhost, &Transform>>,
 ) {
     for (ghost, mut transform, mut draw) in query.iter_mut() {
-        if let Ok(parent_transform) = query_transform.get_component::<Transform>(ghost.parent) {
-            let translation = get_ghost_translation(&arena, &ghost.id);
-            transform.translation = parent_transform.translation + translation;
+        let offset = arena.ghosts.get(ghost.id).cloned().flatten();
+        if let (Some(offset), Ok(parent_transform)) = (
+            offset,
+            query_transform.get_component::<Transform>(ghost.parent),
+        ) {
+            transform.translation = parent_transform.translation + offset.extend(0.0);
             transform.rotation = parent_transform.rotation;
             transform.scale = parent_transform.scale;
             draw.is_visible = true;
@@ -136,41 +250,150 @@ pub fn spriteghost_quadrant_system(
 /// Transform is already local, sync is already handled by Bevy.
 pub fn spriteghost_sync_system() {}
 
+/// Keep the coordinate inside [-half, half], returns true if the edge was hit.
+fn bounce_on_edge(position: &mut f32, speed: &mut f32, half: f32) -> bool {
+    if *position < -half {
+        *position = -half;
+        if *speed < 0.0 {
+            *speed = -*speed * EDGE_RESTITUTION;
+        }
+        true
+    } else if *position > half {
+        *position = half;
+        if *speed > 0.0 {
+            *speed = -*speed * EDGE_RESTITUTION;
+        }
+        true
+    } else {
+        false
+    }
+}
+
 pub fn position_system(
     time: Res<Time>,
     arena: Res<Arena>,
-    mut query: Query<(Mut<Transform>, Mut<Movement>)>,
+    state: Res<GameState>,
+    mut collision_events: ResMut<Events<CollisionEvent>>,
+    mut query: Query<(Entity, Mut<Transform>, Mut<Movement>, Option<&Spaceship>)>,
 ) {
+    if *state != GameState::Playing {
+        return;
+    }
     let elapsed = time.delta_seconds;
-    for (mut transform, mut movement) in query.iter_mut() {
+    for (entity, mut transform, mut movement, ship) in query.iter_mut() {
         transform.translation += Vec3::new(
             movement.speed.x() * elapsed,
             movement.speed.y() * elapsed,
             0.0,
         );
         movement.speed = movement.speed * movement.dampening.powf(time.delta_seconds);
-
-        let half_width = arena.size.x() / 2.0;
-        let half_height = arena.size.y() / 2.0;
-        // Wrap around the world, as a torus.
-        if transform.translation.x() < -half_width && movement.speed.x() < 0.0 {
-            *transform.translation.x_mut() = half_width;
-        } else if transform.translation.x() > half_width && movement.speed.x() > 0.0 {
-            *transform.translation.x_mut() = -half_width;
+        if movement.angvel != 0.0 {
+            transform.rotation =
+                (transform.rotation * Quat::from_rotation_z(movement.angvel * elapsed)).normalize();
         }
-        if transform.translation.y() < -half_height && movement.speed.y() < 0.0 {
-            *transform.translation.y_mut() = half_height;
-        } else if transform.translation.y() > half_height && movement.speed.y() > 0.0 {
-            *transform.translation.y_mut() = -half_height;
+
+        if arena.edge == ArenaEdge::Wrap {
+            let half_width = arena.size.x() / 2.0;
+            let half_height = arena.size.y() / 2.0;
+            // Wrap around the world, as a torus.
+            if transform.translation.x() < -half_width && movement.speed.x() < 0.0 {
+                *transform.translation.x_mut() = half_width;
+            } else if transform.translation.x() > half_width && movement.speed.x() > 0.0 {
+                *transform.translation.x_mut() = -half_width;
+            }
+            if transform.translation.y() < -half_height && movement.speed.y() < 0.0 {
+                *transform.translation.y_mut() = half_height;
+            } else if transform.translation.y() > half_height && movement.speed.y() > 0.0 {
+                *transform.translation.y_mut() = -half_height;
+            }
+        } else {
+            let hit_x = bounce_on_edge(
+                transform.translation.x_mut(),
+                movement.speed.x_mut(),
+                arena.bounds.x() / 2.0,
+            );
+            let hit_y = bounce_on_edge(
+                transform.translation.y_mut(),
+                movement.speed.y_mut(),
+                arena.bounds.y() / 2.0,
+            );
+            let damaging = arena.edge == ArenaEdge::Walls || arena.edge == ArenaEdge::Shrinking;
+            if (hit_x || hit_y) && damaging && ship.is_some() {
+                collision_events.send(CollisionEvent::ShipToWall(entity));
+            }
         }
     }
 }
 
+/// Close the Shrinking walls in.
+pub fn arena_shrink_system(time: Res<Time>, state: Res<GameState>, mut arena: ResMut<Arena>) {
+    if arena.edge != ArenaEdge::Shrinking || *state != GameState::Playing {
+        return;
+    }
+    let shrink = 2.0 * ARENA_SHRINK_RATE * time.delta_seconds;
+    let bounds = arena.bounds - Vec2::splat(shrink);
+    arena.bounds = bounds.max(Vec2::splat(ARENA_MIN_SIZE));
+}
+
+/// Side of the arena for an edge sprite, as a direction from the center.
+pub struct ArenaEdgeSprite(pub Vec2);
+
+/// Draw the edges, when they are not wrapping.
+pub fn spawn_arena_edges(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if arena.edge == ArenaEdge::Wrap {
+        return;
+    }
+    let color = match arena.edge {
+        ArenaEdge::Bounce => Color::rgb(0.4, 0.4, 0.8),
+        _ => Color::rgb(0.9, 0.2, 0.2),
+    };
+    let material = materials.add(color.into());
+    for &side in [
+        Vec2::unit_x(),
+        -Vec2::unit_x(),
+        Vec2::unit_y(),
+        -Vec2::unit_y(),
+    ]
+    .iter()
+    {
+        commands
+            .spawn(SpriteComponents {
+                material: material.clone(),
+                ..Default::default()
+            })
+            .with(ArenaEdgeSprite(side));
+    }
+}
+
+/// Keep the edge sprites on the bounds.
+pub fn arena_edge_sprite_system(
+    arena: Res<Arena>,
+    mut query: Query<(&ArenaEdgeSprite, Mut<Transform>, Mut<Sprite>)>,
+) {
+    for (edge, mut transform, mut sprite) in query.iter_mut() {
+        let half_bounds = arena.bounds / 2.0;
+        transform.translation = (edge.0 * half_bounds).extend(-1.0);
+        sprite.size = if edge.0.x() != 0.0 {
+            Vec2::new(4.0, arena.bounds.y())
+        } else {
+            Vec2::new(arena.bounds.x(), 4.0)
+        };
+    }
+}
+
 pub fn spawn_arena_markers(
     mut commands: Commands,
     arena: Res<Arena>,
+    background: Res<Background>,
     mut materials: ResMut<Assets<ColorMaterial>>,
 ) {
+    if *background != Background::Grid {
+        return;
+    }
     for x in 0..((arena.size.x() / 200.0) as i32) {
         for y in 0..((arena.size.y() / 200.0) as i32) {
             let color = match (x, y) {
@@ -1,3 +1,5 @@
+use super::*;
+
 #[derive(Copy, Clone)]
 pub struct Armor {
     pub max_life: u32,
@@ -12,3 +14,22 @@ impl Armor {
         }
     }
 }
+
+/// Duration without damage after a hit.
+pub const INVULNERABILITY_DURATION: f32 = 1.0;
+
+/// Armor can't be damaged until the timer is finished.
+pub struct Invulnerable(pub Timer);
+
+pub fn invulnerability_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut query: Query<(Entity, Mut<Invulnerable>)>,
+) {
+    for (entity, mut invulnerable) in query.iter_mut() {
+        invulnerable.0.tick(time.delta_seconds);
+        if invulnerable.0.finished {
+            commands.remove_one::<Invulnerable>(entity);
+        }
+    }
+}
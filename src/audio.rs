@@ -0,0 +1,259 @@
+use std::{collections::HashMap, io::Cursor};
+
+use super::*;
+use bevy::audio::AudioSource;
+use rodio::{source::ChannelVolume, Decoder, OutputStream, OutputStreamHandle, Sink};
+use serde::{Deserialize, Serialize};
+
+/// Toggles the mute, not rebindable.
+const MUTE_KEY: KeyCode = KeyCode::M;
+pub const VOLUME_STEP: f32 = 0.1;
+/// Sounds further than this many views from the camera are not played.
+const AUDIBLE_VIEWS: f32 = 1.5;
+/// How far left or right a sound on the screen edge is panned, 1 being one speaker only.
+const PAN_STRENGTH: f32 = 0.8;
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum Sfx {
+    Laser,
+    Explosion,
+    ExplosionFinal,
+    Pickup,
+}
+
+impl Sfx {
+    pub const ALL: [Sfx; 4] = [Sfx::Laser, Sfx::Explosion, Sfx::ExplosionFinal, Sfx::Pickup];
+    fn file(&self) -> &'static str {
+        match self {
+            Sfx::Laser => "sfx_laser1.mp3",
+            Sfx::Explosion => "Explosion.mp3",
+            Sfx::ExplosionFinal => "Explosion_final.mp3",
+            Sfx::Pickup => "zapThreeToneUp.ogg",
+        }
+    }
+    /// Most instances playing at once, extra ones are dropped.
+    fn max_concurrent(&self) -> usize {
+        match self {
+            Sfx::Laser => 4,
+            Sfx::Explosion => 3,
+            Sfx::ExplosionFinal => 2,
+            Sfx::Pickup => 2,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum VolumeBus {
+    Master,
+    Sfx,
+    Music,
+}
+
+impl VolumeBus {
+    pub const ALL: [VolumeBus; 3] = [VolumeBus::Master, VolumeBus::Sfx, VolumeBus::Music];
+    pub fn name(&self) -> &'static str {
+        match self {
+            VolumeBus::Master => "Master volume",
+            VolumeBus::Sfx => "Effects volume",
+            VolumeBus::Music => "Music volume",
+        }
+    }
+}
+
+/// Volumes between 0 and 1, saved in the settings.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct AudioSettings {
+    pub master: f32,
+    pub sfx: f32,
+    pub music: f32,
+    pub muted: bool,
+}
+
+impl Default for AudioSettings {
+    fn default() -> Self {
+        AudioSettings {
+            master: 0.8,
+            sfx: 1.0,
+            music: 0.6,
+            muted: false,
+        }
+    }
+}
+
+impl AudioSettings {
+    pub fn volume(&self, bus: VolumeBus) -> f32 {
+        match bus {
+            VolumeBus::Master => self.master,
+            VolumeBus::Sfx => self.sfx,
+            VolumeBus::Music => self.music,
+        }
+    }
+    pub fn change_volume(&mut self, bus: VolumeBus, delta: f32) {
+        let volume = match bus {
+            VolumeBus::Master => &mut self.master,
+            VolumeBus::Sfx => &mut self.sfx,
+            VolumeBus::Music => &mut self.music,
+        };
+        *volume = (*volume + delta).max(0.0).min(1.0);
+    }
+    /// Volume of a bus, through the master volume and the mute.
+    pub fn output_volume(&self, bus: VolumeBus) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.master * self.volume(bus)
+        }
+    }
+}
+
+pub struct SoundEvent {
+    pub sound: Sfx,
+    /// World position of the source, None for sounds not in the arena.
+    pub position: Option<Vec2>,
+}
+
+/// Volume and left/right gains of a sound heard from the camera, None when too far to be heard.
+/// Sounds on screen are at full volume, then fade out with the wrap-aware distance.
+fn spatialize(arena: &Arena, position: Option<Vec2>) -> Option<(f32, [f32; 2])> {
+    let position = match position {
+        Some(position) if arena.view_size.x() > 0.0 => position,
+        _ => return Some((1.0, [1.0, 1.0])),
+    };
+    let offset = arena.wrapped_delta(arena.view_center, position);
+    let full_volume_distance = arena.view_size.length() / 2.0;
+    let audible_distance = arena.view_size.length() * AUDIBLE_VIEWS;
+    let distance = offset.length();
+    if distance >= audible_distance {
+        return None;
+    }
+    let attenuation = if distance <= full_volume_distance {
+        1.0
+    } else {
+        1.0 - (distance - full_volume_distance) / (audible_distance - full_volume_distance)
+    };
+    let pan = (offset.x() / (arena.view_size.x() / 2.0))
+        .max(-1.0)
+        .min(1.0)
+        * PAN_STRENGTH;
+    Some((attenuation, [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)]))
+}
+
+/// Sound handles, loaded once at startup.
+pub struct SoundHandles(HashMap<Sfx, Handle<AudioSource>>);
+
+pub fn setup_sounds(mut commands: Commands, asset_server: Res<AssetServer>) {
+    let handles = Sfx::ALL
+        .iter()
+        .map(|&sfx| (sfx, asset_server.load(sfx.file())))
+        .collect();
+    commands.insert_resource(SoundHandles(handles));
+}
+
+/// Owns the audio output, rodio streams can't leave the main thread.
+/// bevy Audio has no volume control, so sounds are played here instead.
+pub struct AudioMixer {
+    /// None without an audio device, every sound is then dropped.
+    output: Option<(OutputStream, OutputStreamHandle)>,
+    sound_reader: EventReader<SoundEvent>,
+    playing: Vec<(Sfx, Sink)>,
+    /// Layers of music, see music_system.
+    pub music: Vec<MusicPlayer>,
+}
+
+impl AudioMixer {
+    fn new() -> Self {
+        let output = match OutputStream::try_default() {
+            Ok(output) => Some(output),
+            Err(e) => {
+                println!("No audio output, sounds are disabled: {}", e);
+                None
+            }
+        };
+        AudioMixer {
+            output,
+            sound_reader: EventReader::default(),
+            playing: Vec::new(),
+            music: Vec::new(),
+        }
+    }
+    pub fn output_handle(&self) -> Option<OutputStreamHandle> {
+        self.output.as_ref().map(|(_, handle)| handle.clone())
+    }
+    fn play(&mut self, sfx: Sfx, source: &AudioSource, volume: f32, channels: [f32; 2]) {
+        let handle = match &self.output {
+            Some((_, handle)) => handle,
+            None => return,
+        };
+        self.playing.retain(|(_, sink)| !sink.empty());
+        let count = self
+            .playing
+            .iter()
+            .filter(|(playing, _)| *playing == sfx)
+            .count();
+        if count >= sfx.max_concurrent() || volume <= 0.0 {
+            return;
+        }
+        let decoder = match Decoder::new(Cursor::new(source.clone())) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                println!("Failed to decode {}: {}", sfx.file(), e);
+                return;
+            }
+        };
+        if let Ok(sink) = Sink::try_new(handle) {
+            sink.set_volume(volume);
+            sink.append(ChannelVolume::new(decoder, channels.to_vec()));
+            self.playing.push((sfx, sink));
+        }
+    }
+}
+
+pub fn setup_audio_mixer(_world: &mut World, resources: &mut Resources) {
+    resources.insert_thread_local(AudioMixer::new());
+}
+
+/// Play the sounds requested this frame.
+pub fn audio_mixer_system(_world: &mut World, resources: &mut Resources) {
+    let mut mixer = resources.get_thread_local_mut::<AudioMixer>().unwrap();
+    let sound_events = resources.get::<Events<SoundEvent>>().unwrap();
+    let handles = resources.get::<SoundHandles>().unwrap();
+    let sources = resources.get::<Assets<AudioSource>>().unwrap();
+    let settings = resources.get::<Settings>().unwrap();
+    let arena = resources.get::<Arena>().unwrap();
+    let volume = settings.audio.output_volume(VolumeBus::Sfx);
+    let sounds: Vec<(Sfx, Option<Vec2>)> = mixer
+        .sound_reader
+        .iter(&sound_events)
+        .map(|event| (event.sound, event.position))
+        .collect();
+    for (sfx, position) in sounds {
+        let (attenuation, channels) = match spatialize(&arena, position) {
+            Some(spatial) => spatial,
+            None => continue,
+        };
+        // Still loading, the sound is skipped rather than played late.
+        if let Some(source) = sources.get(&handles.0[&sfx]) {
+            mixer.play(sfx, source, volume * attenuation, channels);
+        }
+    }
+}
+
+pub fn mute_system(
+    state: Res<GameState>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mut settings: ResMut<Settings>,
+    menu: Res<SettingsMenu>,
+    (name_entry, run, high_scores): (Res<NameEntry>, Res<RunStats>, Res<HighScores>),
+) {
+    // The key could be the one being captured, or a letter of a high score name.
+    if *state == GameState::Settings && menu.capture.is_some()
+        || name_entry.is_active(*state, run.score, &high_scores)
+    {
+        return;
+    }
+    if keyboard_input.just_pressed(MUTE_KEY) {
+        settings.audio.muted = !settings.audio.muted;
+        settings.save();
+    }
+}
@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+
+use bevy::input::mouse::MouseWheel;
+
+use super::*;
+
+/// How fast the camera catches up with its target, per second.
+const CAMERA_SMOOTHING: f32 = 5.0;
+/// The camera looks where the ship will be in this many seconds.
+const LOOK_AHEAD_TIME: f32 = 0.4;
+const LOOK_AHEAD_MAX: f32 = 250.0;
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 2.0;
+const ZOOM_STEP: f32 = 0.1;
+const ZOOM_SMOOTHING: f32 = 10.0;
+/// Trauma lost per second.
+const TRAUMA_DECAY: f32 = 1.2;
+const MAX_SHAKE_OFFSET: f32 = 30.0;
+const MAX_SHAKE_ANGLE: f32 = 0.05;
+const SHAKE_FREQUENCY: f32 = 20.0;
+/// Space kept around the followed entities when zooming out to show them all.
+const FIT_MARGIN: f32 = 300.0;
+
+/// Add trauma to the cameras, the shake grows with the square of the trauma.
+pub struct CameraShakeEvent {
+    pub trauma: f32,
+}
+
+/// State of a camera following a FollowedCamera entity.
+pub struct CameraRig {
+    /// Position looked at, without the shake.
+    pub focus: Vec2,
+    pub zoom: f32,
+    pub target_zoom: f32,
+    /// Between 0 and 1.
+    pub trauma: f32,
+    shake_time: f32,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        CameraRig {
+            focus: Vec2::zero(),
+            zoom: 1.0,
+            target_zoom: 1.0,
+            trauma: 0.0,
+            shake_time: 0.0,
+        }
+    }
+}
+
+impl CameraRig {
+    pub fn add_trauma(&mut self, trauma: f32) {
+        self.trauma = (self.trauma + trauma).min(1.0);
+    }
+    pub fn zoom_by(&mut self, steps: f32) {
+        self.target_zoom = (self.target_zoom - steps * ZOOM_STEP)
+            .max(MIN_ZOOM)
+            .min(MAX_ZOOM);
+    }
+    /// Camera offset and angle due to the shake.
+    fn shake(&self) -> (Vec2, f32) {
+        let shake = self.trauma * self.trauma;
+        // Sum of sines as a cheap smooth noise, each value on its own frequencies.
+        let noise = |seed: f32| {
+            let t = self.shake_time * SHAKE_FREQUENCY;
+            ((t + seed).sin() + (t * 1.7 + seed * 3.1).sin()) / 2.0
+        };
+        (
+            Vec2::new(noise(0.0), noise(10.0)) * MAX_SHAKE_OFFSET * shake,
+            noise(20.0) * MAX_SHAKE_ANGLE * shake,
+        )
+    }
+}
+
+pub fn camera_follow_system(
+    time: Res<Time>,
+    (arena, windows): (Res<Arena>, Res<Windows>),
+    (mut wheel_events, mouse_wheel): (Local<EventReader<MouseWheel>>, Res<Events<MouseWheel>>),
+    (mut shake_events, camera_shakes): (
+        Local<EventReader<CameraShakeEvent>>,
+        Res<Events<CameraShakeEvent>>,
+    ),
+    followed: Query<(&FollowedCamera, &Transform, Option<&Movement>)>,
+    mut cameras: Query<(Mut<CameraRig>, Mut<Transform>)>,
+) {
+    let dt = time.delta_seconds;
+    let wheel: f32 = wheel_events.iter(&mouse_wheel).map(|event| event.y).sum();
+    let trauma: f32 = shake_events
+        .iter(&camera_shakes)
+        .map(|event| event.trauma)
+        .sum();
+    let window_size = windows
+        .get_primary()
+        .map(|window| Vec2::new(window.width() as f32, window.height() as f32));
+    let mut targets: HashMap<Entity, Vec<Vec2>> = HashMap::new();
+    for (followed_camera, transform, movement) in followed.iter() {
+        let mut look_ahead = movement.map_or(Vec2::zero(), |m| m.speed * LOOK_AHEAD_TIME);
+        if look_ahead.length() > LOOK_AHEAD_MAX {
+            look_ahead = look_ahead.normalize() * LOOK_AHEAD_MAX;
+        }
+        targets
+            .entry(followed_camera.0)
+            .or_insert_with(Vec::new)
+            .push(transform.translation.truncate() + look_ahead);
+    }
+    for (camera, positions) in targets {
+        if let Ok((mut rig, mut transform)) = cameras.get_mut(camera) {
+            rig.zoom_by(wheel);
+            rig.add_trauma(trauma);
+            // Wrap-aware average, players on both sides of an edge are framed through it.
+            let first = positions[0];
+            let target = first
+                + positions.iter().fold(Vec2::zero(), |sum, &position| {
+                    sum + arena.wrapped_delta(first, position)
+                }) / positions.len() as f32;
+            // Zoom out when needed to keep every followed entity in view, past MAX_ZOOM which
+            // only bounds the wheel: a player is never left out of the shared view.
+            let fit_zoom = match window_size {
+                Some(window_size) => positions
+                    .iter()
+                    .map(|&position| {
+                        let delta = arena.wrapped_delta(target, position);
+                        let needed = Vec2::new(delta.x().abs(), delta.y().abs()) * 2.0
+                            + Vec2::splat(FIT_MARGIN);
+                        let zoom = needed / (window_size * CAMERA_SCALE);
+                        zoom.x().max(zoom.y())
+                    })
+                    .fold(0.0, f32::max),
+                None => 0.0,
+            };
+            let target_zoom = rig.target_zoom.max(fit_zoom);
+            // Move toward the nearest copy of the target, so crossing an edge does not pan the whole arena.
+            let delta = arena.wrapped_delta(rig.focus, target);
+            let focus = rig.focus + delta * (1.0 - (-CAMERA_SMOOTHING * dt).exp());
+            rig.focus = target - arena.wrapped_delta(focus, target);
+            rig.zoom += (target_zoom - rig.zoom) * (1.0 - (-ZOOM_SMOOTHING * dt).exp());
+            rig.trauma = (rig.trauma - TRAUMA_DECAY * dt).max(0.0);
+            rig.shake_time += dt;
+            let (offset, angle) = rig.shake();
+            let z = transform.translation.z();
+            transform.translation = (rig.focus + offset).extend(z);
+            transform.rotation = Quat::from_rotation_z(angle);
+            transform.scale = Vec3::splat(CAMERA_SCALE * rig.zoom);
+        }
+    }
+}
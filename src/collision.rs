@@ -69,11 +69,15 @@ pub fn collide_position_system(
 pub enum CollisionEvent {
     MissileToEnemy(Entity, Entity),
     ShipToLoot(Entity, Entity),
+    ShipToEnemy(Entity, Entity),
+    /// Ship hitting damaging arena edges.
+    ShipToWall(Entity),
 }
 pub fn collision_system(
     mut world: ResMut<CollisionWorld<f32, Entity>>,
     mut cursor_selection: ResMut<CursorSelection>,
     mut collision_events: ResMut<Events<CollisionEvent>>,
+    mut impact_events: ResMut<Events<ImpactEvent>>,
     mut selection_changed_events: ResMut<Events<CursorSelectionEvent>>,
     collider_types: Query<&ColliderType>,
 ) {
@@ -81,11 +85,18 @@ pub fn collision_system(
     let mut enemies = HashSet::new();
     let mut loots = HashSet::new();
     for (h1, h2, _, manifold) in world.contact_pairs(true) {
-        if let Some(_tracked_contact) = manifold.deepest_contact() {
+        if let Some(tracked_contact) = manifold.deepest_contact() {
             let e1 = *world.collision_object(h1).unwrap().data();
             let e2 = *world.collision_object(h2).unwrap().data();
             if let Ok(&t1) = collider_types.get_component::<ColliderType>(e1) {
                 if let Ok(&t2) = collider_types.get_component::<ColliderType>(e2) {
+                    let normal = tracked_contact.contact.normal;
+                    impact_events.send(ImpactEvent {
+                        e1,
+                        e2,
+                        normal: Vec2::new(normal.x, normal.y),
+                        depth: tracked_contact.contact.depth,
+                    });
                     if t1 == ColliderType::Missile && t2 == ColliderType::Enemy {
                         collision_events.send(CollisionEvent::MissileToEnemy(e1, e2))
                     }
@@ -98,6 +109,12 @@ pub fn collision_system(
                     if t2 == ColliderType::Ship && t1 == ColliderType::Loot {
                         collision_events.send(CollisionEvent::ShipToLoot(e2, e1))
                     }
+                    if t1 == ColliderType::Ship && t2 == ColliderType::Enemy {
+                        collision_events.send(CollisionEvent::ShipToEnemy(e1, e2))
+                    }
+                    if t2 == ColliderType::Ship && t1 == ColliderType::Enemy {
+                        collision_events.send(CollisionEvent::ShipToEnemy(e2, e1))
+                    }
                     if t1 == ColliderType::Cursor {
                         if t2 == ColliderType::Enemy {
                             enemies.insert(e2);
@@ -132,14 +149,23 @@ pub fn collision_event_system(
     mut commands: Commands,
     mut events: Local<EventReader<CollisionEvent>>,
     collision_events: ResMut<Events<CollisionEvent>>,
-    (asset_server, audio): (Res<AssetServer>, Res<Audio>),
-    mut xp_events: ResMut<Events<XpEvent>>,
-    mut loot_events: ResMut<Events<LootEvent>>,
+    mut sound_events: ResMut<Events<SoundEvent>>,
+    (mut xp_events, mut loot_events, mut shake_events, mut particle_events, mut hit_events): (
+        ResMut<Events<XpEvent>>,
+        ResMut<Events<LootEvent>>,
+        ResMut<Events<CameraShakeEvent>>,
+        ResMut<Events<ParticleEvent>>,
+        ResMut<Events<HitEvent>>,
+    ),
+    (mut wave, mut run, mut state): (ResMut<Wave>, ResMut<RunStats>, ResMut<GameState>),
     damage_dealers: Query<&DamageDealer>,
     mut armors: Query<Mut<Armor>>,
-    enemies: Query<&Enemy>,
-    loots: Query<&Loot>,
+    (enemies, wave_members): (Query<&Enemy>, Query<&WaveMember>),
+    (loots, loot_owners): (Query<&Loot>, Query<&LootOwner>),
+    ships: Query<With<UserControlled, Entity>>,
     mut weapons: Query<Mut<Weapon>>,
+    mut inventories: Query<Mut<Inventory>>,
+    (invulnerables, in_nebulas): (Query<&Invulnerable>, Query<&InNebula>),
     transforms: Query<&Transform>,
 ) {
     for event in events.iter(&collision_events) {
@@ -149,49 +175,176 @@ pub fn collision_event_system(
                 let mut armor = armors.get_component_mut::<Armor>(*e2).unwrap();
                 // Multiple damaging at the same frame can happen, before despawning
                 if armor.life > 0 {
-                    armor.life -= damage_dealer.value;
-                    println!("TOUCHED {}/{}", armor.life, armor.max_life);
+                    armor.life = armor.life.saturating_sub(damage_dealer.value);
                     commands.despawn_from_arena(*e1);
+                    let enemy_transform = transforms
+                        .get_component::<Transform>(*e2)
+                        .expect("Enemy without a transform.");
+                    let enemy_translation = enemy_transform.translation;
+                    let missile_position = transforms
+                        .get_component::<Transform>(*e1)
+                        .map_or(enemy_translation, |transform| transform.translation)
+                        .truncate();
+                    hit_events.send(HitEvent {
+                        entity: *e2,
+                        position: missile_position,
+                        damage: damage_dealer.value,
+                        kind: damage_dealer.kind,
+                    });
                     if armor.life <= 0 {
                         commands.despawn_from_arena(*e2);
-                        audio.play(asset_server.load("Explosion_final.mp3"));
+                        sound_events.send(SoundEvent {
+                            sound: Sfx::ExplosionFinal,
+                            position: Some(enemy_translation.truncate()),
+                        });
+                        shake_events.send(CameraShakeEvent { trauma: 0.3 });
+                        particle_events.send(ParticleEvent {
+                            effect: ParticleEffect::Explosion,
+                            position: enemy_translation.truncate(),
+                            direction: Vec2::zero(),
+                            velocity: Vec2::zero(),
+                            size: enemy_transform.scale.x() * 2.0,
+                            color: None,
+                        });
                         if let Ok(enemy) = enemies.get_component::<Enemy>(*e2) {
+                            if wave_members.get_component::<WaveMember>(*e2).is_ok() {
+                                wave.enemy_destroyed();
+                            }
+                            run.enemy_killed(enemy, wave.number);
                             xp_events.send(XpEvent {
                                 xp: enemy.xp,
                                 source: damage_dealer.source,
                             });
-                            let enemy_translation = transforms
-                                .get_component::<Transform>(*e2)
-                                .expect("Enemy without a transform.")
-                                .translation;
                             loot_events.send(LootEvent {
                                 position: enemy_translation.truncate(),
+                                credits: enemy.xp * 5,
+                                owner: Some(damage_dealer.source),
                             });
                         }
                     } else {
-                        audio.play(asset_server.load("Explosion.mp3"));
+                        sound_events.send(SoundEvent {
+                            sound: Sfx::Explosion,
+                            position: Some(missile_position),
+                        });
+                        shake_events.send(CameraShakeEvent { trauma: 0.1 });
+                        // Sparks fly back toward the shooter, no direction on a dead center hit.
+                        let impact = missile_position - enemy_translation.truncate();
+                        particle_events.send(ParticleEvent {
+                            effect: ParticleEffect::Impact,
+                            position: missile_position,
+                            direction: if impact.length() > f32::EPSILON {
+                                impact.normalize()
+                            } else {
+                                Vec2::zero()
+                            },
+                            velocity: Vec2::zero(),
+                            size: 1.0,
+                            color: None,
+                        });
                     }
                 }
             }
             CollisionEvent::ShipToLoot(e1, e2) => {
                 let loot = loots.get_component::<Loot>(*e2).unwrap();
+                if let Ok(loot_owner) = loot_owners.get_component::<LootOwner>(*e2) {
+                    if loot_owner.owner != *e1 {
+                        // Still reserved for the co-op partner who scored the kill.
+                        continue;
+                    }
+                }
+                if let Ok(mut inventory) = inventories.get_component_mut::<Inventory>(*e1) {
+                    if !inventory.store(*loot) {
+                        // No room left, the loot stays in the arena.
+                        continue;
+                    }
+                } else if let Ok(mut weapon) = weapons.get_component_mut::<Weapon>(*e1) {
+                    loot.apply(&mut weapon);
+                }
                 commands.despawn_from_arena(*e2);
-                match loot {
-                    Loot::IncreasedRateOfFire(p) => {
-                        if let Ok(mut weapon) = weapons.get_component_mut::<Weapon>(*e1) {
-                            weapon.fire_timer.duration =
-                                weapon.fire_timer.duration / (*p as f32 / 100.0);
+                let loot_position = transforms
+                    .get_component::<Transform>(*e2)
+                    .ok()
+                    .map(|transform| transform.translation.truncate());
+                sound_events.send(SoundEvent {
+                    sound: Sfx::Pickup,
+                    position: loot_position,
+                });
+                if let Some(loot_position) = loot_position {
+                    particle_events.send(ParticleEvent {
+                        effect: ParticleEffect::Sparkle,
+                        position: loot_position,
+                        direction: Vec2::zero(),
+                        velocity: Vec2::zero(),
+                        size: 1.0,
+                        color: Some(loot.get_color()),
+                    });
+                }
+            }
+            CollisionEvent::ShipToEnemy(e1, _) | CollisionEvent::ShipToWall(e1) => {
+                if invulnerables.get_component::<Invulnerable>(*e1).is_ok() {
+                    continue;
+                }
+                let mut destroyed = false;
+                if let Ok(mut armor) = armors.get_component_mut::<Armor>(*e1) {
+                    if armor.life > 0 {
+                        armor.life -= 1;
+                        run.wave_damage_taken = true;
+                        let invulnerability = if in_nebulas.get_component::<InNebula>(*e1).is_ok() {
+                            INVULNERABILITY_DURATION * NEBULA_INVULNERABILITY_FACTOR
+                        } else {
+                            INVULNERABILITY_DURATION
+                        };
+                        commands.insert_one(
+                            *e1,
+                            Invulnerable(Timer::from_seconds(invulnerability, false)),
+                        );
+                        let ship_position = transforms
+                            .get_component::<Transform>(*e1)
+                            .ok()
+                            .map(|transform| transform.translation.truncate());
+                        if let Ok(transform) = transforms.get_component::<Transform>(*e1) {
+                            hit_events.send(HitEvent {
+                                entity: *e1,
+                                position: transform.translation.truncate(),
+                                damage: 1,
+                                kind: DamageKind::Collision,
+                            });
+                            particle_events.send(ParticleEvent {
+                                effect: ParticleEffect::Explosion,
+                                position: transform.translation.truncate(),
+                                direction: Vec2::zero(),
+                                velocity: Vec2::zero(),
+                                size: if armor.life == 0 { 1.5 } else { 0.5 },
+                                color: None,
+                            });
                         }
-                    }
-                    Loot::IncreasedMunitionDuration(p) => {
-                        if let Ok(mut weapon) = weapons.get_component_mut::<Weapon>(*e1) {
-                            weapon.munition_lifespan =
-                                weapon.munition_lifespan * (*p as f32 / 100.0);
+                        if armor.life == 0 {
+                            commands.despawn_from_arena(*e1);
+                            sound_events.send(SoundEvent {
+                                sound: Sfx::ExplosionFinal,
+                                position: ship_position,
+                            });
+                            shake_events.send(CameraShakeEvent { trauma: 1.0 });
+                            destroyed = true;
+                        } else {
+                            sound_events.send(SoundEvent {
+                                sound: Sfx::Explosion,
+                                position: ship_position,
+                            });
+                            shake_events.send(CameraShakeEvent { trauma: 0.5 });
                         }
                     }
-                    _ => {}
                 }
-                audio.play(asset_server.load("zapThreeToneUp.ogg"));
+                // In co-op, the run goes on while a partner is alive.
+                if destroyed
+                    && !ships.iter().any(|ship| {
+                        armors
+                            .get_component::<Armor>(ship)
+                            .map_or(false, |armor| armor.life > 0)
+                    })
+                {
+                    *state = GameState::GameOver;
+                }
             }
         }
     }
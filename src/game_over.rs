@@ -0,0 +1,70 @@
+use super::*;
+
+/// Tag component for the game over panel root node.
+pub struct GameOverPanel;
+pub enum GameOverText {
+    Title,
+    Run,
+    Profile,
+    NameEntry,
+    HighScore(usize),
+    Quit,
+}
+
+pub fn game_over_ui_system(
+    mut commands: Commands,
+    state: Res<GameState>,
+    (run, wave, profile, seed): (Res<RunStats>, Res<Wave>, Res<Profile>, Res<GameSeed>),
+    (name_entry, high_scores): (Res<NameEntry>, Res<HighScores>),
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    panels: Query<With<GameOverPanel, Entity>>,
+    mut texts: Query<(&GameOverText, Mut<Text>)>,
+) {
+    if *state != GameState::GameOver {
+        return;
+    }
+    if panels.iter().next().is_none() {
+        let mut lines = vec![
+            GameOverText::Title,
+            GameOverText::Run,
+            GameOverText::Profile,
+            GameOverText::NameEntry,
+        ];
+        lines.extend((0..HIGH_SCORES_COUNT).map(GameOverText::HighScore));
+        lines.push(GameOverText::Quit);
+        let font = asset_server.load("FiraSans-Bold.ttf");
+        spawn_panel(&mut commands, &mut materials, font, GameOverPanel, lines);
+        return;
+    }
+    let entering_name = name_entry.is_active(*state, run.score, &high_scores);
+    for (line, mut text) in texts.iter_mut() {
+        text.value = match line {
+            GameOverText::Title => "Game over".to_string(),
+            GameOverText::Run => format!(
+                "Wave {} - {} kills - score {} - seed {}",
+                wave.number, run.kills, run.score, seed.0
+            ),
+            GameOverText::Profile => format!(
+                "Best score {} - highest wave {} - {} meta credits",
+                profile.best_score, profile.highest_wave, profile.meta_currency
+            ),
+            GameOverText::NameEntry if entering_name => {
+                format!("New high score! Name: {}_", name_entry.name)
+            }
+            GameOverText::NameEntry => "".to_string(),
+            GameOverText::HighScore(rank) => match high_scores.entries.get(*rank) {
+                Some(entry) => format!(
+                    "{}. {} - {} (wave {})",
+                    rank + 1,
+                    entry.name,
+                    entry.score,
+                    entry.wave
+                ),
+                None => "".to_string(),
+            },
+            GameOverText::Quit if entering_name => "Return to validate".to_string(),
+            GameOverText::Quit => "F4 to quit".to_string(),
+        };
+    }
+}
@@ -0,0 +1,209 @@
+use super::*;
+
+/// Stick values below this are ignored, worn sticks rarely rest on zero.
+const STICK_DEADZONE: f32 = 0.2;
+/// Gamepad buttons of the inventory actions, ACTION_USE_ITEM and ACTION_NEXT_ITEM on the keyboard.
+pub const GAMEPAD_USE_ITEM: GamepadButtonType = GamepadButtonType::West;
+pub const GAMEPAD_NEXT_ITEM: GamepadButtonType = GamepadButtonType::North;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum InputMode {
+    Mouse,
+    Gamepad,
+}
+
+/// Device steering a ship.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum InputDevice {
+    /// The last device used, for a single player.
+    Auto,
+    KeyboardMouse,
+    Gamepad,
+    /// Steered by a networked client.
+    Remote,
+}
+
+impl InputDevice {
+    /// Whether the ship currently follows the gamepad.
+    pub fn uses_gamepad(&self, gamepad_state: &GamepadState) -> bool {
+        match self {
+            InputDevice::Auto => gamepad_state.mode == InputMode::Gamepad,
+            InputDevice::KeyboardMouse | InputDevice::Remote => false,
+            InputDevice::Gamepad => true,
+        }
+    }
+}
+
+/// Gamepad used by the player, and its sticks after the deadzone.
+pub struct GamepadState {
+    pub gamepad: Option<Gamepad>,
+    /// Switched to the last device used.
+    pub mode: InputMode,
+    pub left_stick: Vec2,
+    pub right_stick: Vec2,
+}
+
+impl GamepadState {
+    /// Whether the button of the tracked gamepad was pressed this frame.
+    pub fn just_pressed(
+        &self,
+        gamepad_buttons: &Input<GamepadButton>,
+        button_type: GamepadButtonType,
+    ) -> bool {
+        self.gamepad.map_or(false, |gamepad| {
+            gamepad_buttons.just_pressed(GamepadButton(gamepad, button_type))
+        })
+    }
+}
+
+impl Default for GamepadState {
+    fn default() -> Self {
+        GamepadState {
+            gamepad: None,
+            mode: InputMode::Mouse,
+            left_stick: Vec2::zero(),
+            right_stick: Vec2::zero(),
+        }
+    }
+}
+
+/// Radial deadzone, rescaled so the output still covers 0 to 1.
+fn apply_deadzone(stick: Vec2) -> Vec2 {
+    let length = stick.length();
+    if length < STICK_DEADZONE {
+        return Vec2::zero();
+    }
+    stick / length * ((length - STICK_DEADZONE) / (1.0 - STICK_DEADZONE)).min(1.0)
+}
+
+fn read_stick(
+    axes: &Axis<GamepadAxis>,
+    gamepad: Gamepad,
+    x: GamepadAxisType,
+    y: GamepadAxisType,
+) -> Vec2 {
+    let value = |axis_type| axes.get(&GamepadAxis(gamepad, axis_type)).unwrap_or(0.0);
+    apply_deadzone(Vec2::new(value(x), value(y)))
+}
+
+/// Track the gamepad, and switch between mouse and gamepad on the last device used.
+pub fn gamepad_input_system(
+    mut gamepad_state: ResMut<GamepadState>,
+    (mut gamepad_reader, gamepad_events): (
+        Local<EventReader<GamepadEvent>>,
+        Res<Events<GamepadEvent>>,
+    ),
+    (mut cursor_reader, cursor_events): (Local<EventReader<CursorMoved>>, Res<Events<CursorMoved>>),
+    axes: Res<Axis<GamepadAxis>>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+) {
+    for GamepadEvent(gamepad, event_type) in gamepad_reader.iter(&gamepad_events) {
+        match event_type {
+            GamepadEventType::Connected => {
+                if gamepad_state.gamepad.is_none() {
+                    gamepad_state.gamepad = Some(*gamepad);
+                }
+            }
+            GamepadEventType::Disconnected => {
+                if gamepad_state.gamepad == Some(*gamepad) {
+                    *gamepad_state = GamepadState::default();
+                }
+            }
+        }
+    }
+    let gamepad = match gamepad_state.gamepad {
+        Some(gamepad) => gamepad,
+        None => return,
+    };
+    gamepad_state.left_stick = read_stick(
+        &axes,
+        gamepad,
+        GamepadAxisType::LeftStickX,
+        GamepadAxisType::LeftStickY,
+    );
+    gamepad_state.right_stick = read_stick(
+        &axes,
+        gamepad,
+        GamepadAxisType::RightStickX,
+        GamepadAxisType::RightStickY,
+    );
+    let gamepad_used = gamepad_state.left_stick != Vec2::zero()
+        || gamepad_state.right_stick != Vec2::zero()
+        || gamepad_buttons
+            .get_just_pressed()
+            .any(|button| button.0 == gamepad);
+    let mouse_used = cursor_reader.iter(&cursor_events).next().is_some()
+        || mouse_input.get_just_pressed().next().is_some()
+        || keyboard_input.get_just_pressed().next().is_some();
+    let mode = if gamepad_used {
+        InputMode::Gamepad
+    } else if mouse_used {
+        InputMode::Mouse
+    } else {
+        gamepad_state.mode
+    };
+    if mode != gamepad_state.mode {
+        gamepad_state.mode = mode;
+    }
+}
+
+/// Thrust and strafe with the left stick, fire with the triggers.
+pub fn gamepad_action_system(
+    time: Res<Time>,
+    state: Res<GameState>,
+    gamepad_state: Res<GamepadState>,
+    gamepad_buttons: Res<Input<GamepadButton>>,
+    mut fire_weapon_events: ResMut<Events<FireWeaponEvent>>,
+    mut query_spaceship: Query<
+        With<
+            UserControlled,
+            (
+                Entity,
+                &InputDevice,
+                &Spaceship,
+                Mut<Movement>,
+                &Transform,
+                Mut<Weapon>,
+            ),
+        >,
+    >,
+    mut emitters: Query<Mut<ParticleEmitter>>,
+) {
+    let gamepad = match gamepad_state.gamepad {
+        Some(gamepad) => gamepad,
+        None => return,
+    };
+    if *state != GameState::Playing {
+        return;
+    }
+    let fire = [
+        GamepadButtonType::RightTrigger2,
+        GamepadButtonType::LeftTrigger2,
+    ]
+    .iter()
+    .any(|&button_type| gamepad_buttons.pressed(GamepadButton(gamepad, button_type)));
+    let stick = gamepad_state.left_stick;
+    for (ship_entity, device, ship, mut movement, ship_transform, mut weapon) in
+        query_spaceship.iter_mut()
+    {
+        if !device.uses_gamepad(&gamepad_state) {
+            continue;
+        }
+        if fire {
+            fire_weapon(ship_entity, &mut weapon, &mut fire_weapon_events);
+        }
+        // Up thrusts forward, sideways strafes, both scaled by the stick tilt.
+        let forward = (ship_transform.rotation * Vec3::unit_x()).truncate();
+        let left = Vec2::new(-forward.y(), forward.x());
+        movement.speed += (forward * stick.y() * ship.max_linvel
+            - left * stick.x() * ship.max_latvel)
+            * time.delta_seconds;
+        if stick.y() > 0.0 {
+            if let Ok(mut emitter) = emitters.get_component_mut::<ParticleEmitter>(ship_entity) {
+                emitter.active = true;
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use rand::rngs::StdRng;
+
+use super::*;
+
+/// Closest distance used for gravity, so wells don't fling bodies at infinite speed.
+const GRAVITY_MIN_DISTANCE: f32 = 60.0;
+/// Hazards are kept away from the player spawn.
+const HAZARD_SPAWN_CLEARANCE: f32 = 500.0;
+/// Positions drawn before settling for the farthest one, in an arena too small for the clearance.
+const HAZARD_SPAWN_ATTEMPTS: u32 = 32;
+/// Part of the invulnerability after a hit left to ships in a nebula disabling shields.
+pub const NEBULA_INVULNERABILITY_FACTOR: f32 = 0.25;
+
+/// Black hole or planet, attracting every entity with a Movement.
+pub struct GravityWell {
+    /// Acceleration at a distance of 1, decreasing with the square of the distance.
+    pub strength: f32,
+    pub radius: f32,
+}
+
+/// Zone slowing ships down, and possibly disabling their shields.
+pub struct Nebula {
+    pub radius: f32,
+    /// Extra speed factor after 1s inside the nebula.
+    pub dampening: f32,
+    pub disables_shields: bool,
+}
+
+/// Tag component for ships inside a nebula disabling shields,
+/// their invulnerability after a hit is shortened.
+pub struct InNebula;
+
+fn random_hazard_position(rng: &mut StdRng, arena: &Arena) -> Vec2 {
+    let mut farthest = (Vec2::zero(), 0.0);
+    for _ in 0..HAZARD_SPAWN_ATTEMPTS {
+        let position = Vec2::new(
+            rng.gen_range(-arena.size.x() / 2.0, arena.size.x() / 2.0),
+            rng.gen_range(-arena.size.y() / 2.0, arena.size.y() / 2.0),
+        );
+        let distance = arena.wrapped_delta(Vec2::zero(), position).length();
+        if distance > HAZARD_SPAWN_CLEARANCE {
+            return position;
+        }
+        if distance > farthest.1 {
+            farthest = (position, distance);
+        }
+    }
+    farthest.0
+}
+
+/// Place gravity wells, nebulas and asteroid fields, from the game seed.
+pub fn spawn_hazards(
+    mut commands: Commands,
+    seed: Res<GameSeed>,
+    arena: Res<Arena>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut outline_materials: ResMut<Assets<OutlineMaterial>>,
+    (mut collide_world, collide_groups): (ResMut<CollisionWorld<f32, Entity>>, Res<CollideGroups>),
+    network: Res<Network>,
+) {
+    // Hazards are not replicated, a client only sees their effect on the ships.
+    if !network.simulates_waves() {
+        return;
+    }
+    let mut rng = StdRng::seed_from_u64(seed.0);
+    let sphere = asset_server.load("sprite_sphere_256x256.png");
+    for _ in 0..rng.gen_range(1, 3) {
+        let position = random_hazard_position(&mut rng, &arena);
+        let black_hole = rng.gen_bool(0.5);
+        let (radius, strength, color) = if black_hole {
+            (600.0, 4.0e6, Color::rgb(0.2, 0.0, 0.3))
+        } else {
+            (400.0, 1.5e6, Color::rgb(0.3, 0.5, 0.8))
+        };
+        // The sprite is the core, the well reaches further.
+        let core_size = radius / 6.0;
+        commands
+            .spawn_with_ghosts(SpriteComponents {
+                material: materials.add(ColorMaterial::modulated_texture(sphere.clone(), color)),
+                transform: Transform {
+                    translation: position.extend(-9.0),
+                    scale: Vec3::splat(core_size / 256.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with(GravityWell { strength, radius });
+    }
+    for _ in 0..rng.gen_range(2, 4) {
+        let position = random_hazard_position(&mut rng, &arena);
+        let radius = rng.gen_range(200.0, 400.0);
+        let disables_shields = rng.gen_bool(0.5);
+        let color = if disables_shields {
+            Color::rgba(0.8, 0.3, 0.6, 0.25)
+        } else {
+            Color::rgba(0.3, 0.6, 0.8, 0.25)
+        };
+        commands
+            .spawn_with_ghosts(SpriteComponents {
+                material: materials.add(ColorMaterial::modulated_texture(sphere.clone(), color)),
+                transform: Transform {
+                    translation: position.extend(-9.5),
+                    scale: Vec3::splat(2.0 * radius / 256.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with(Nebula {
+                radius,
+                dampening: 0.3,
+                disables_shields,
+            });
+    }
+    for _ in 0..rng.gen_range(1, 3) {
+        let center = random_hazard_position(&mut rng, &arena);
+        let angle = rng.gen_range(0.0, 2.0 * PI);
+        let drift = Vec2::new(angle.cos(), angle.sin()) * rng.gen_range(5.0, 20.0);
+        for _ in 0..rng.gen_range(5, 9) {
+            let offset_angle = rng.gen_range(0.0, 2.0 * PI);
+            let offset =
+                Vec2::new(offset_angle.cos(), offset_angle.sin()) * rng.gen_range(0.0, 250.0);
+            spawn_asteroid_entity(
+                &mut commands,
+                &asset_server,
+                &mut materials,
+                &mut outline_materials,
+                &mut collide_world,
+                &collide_groups,
+                AsteroidSpec {
+                    position: center + offset,
+                    speed: drift,
+                    angvel: rng.gen_range(-0.3, 0.3),
+                    scale: rng.gen_range(0.2, 0.35),
+                },
+            );
+        }
+    }
+}
+
+pub fn gravity_system(
+    time: Res<Time>,
+    arena: Res<Arena>,
+    state: Res<GameState>,
+    wells: Query<(&GravityWell, &Transform)>,
+    mut bodies: Query<(&Transform, Mut<Movement>)>,
+) {
+    if *state != GameState::Playing {
+        return;
+    }
+    for (well, well_transform) in wells.iter() {
+        let well_position = well_transform.translation.truncate();
+        for (transform, mut movement) in bodies.iter_mut() {
+            let delta = arena.wrapped_delta(transform.translation.truncate(), well_position);
+            let distance = delta.length();
+            if distance > well.radius || distance == 0.0 {
+                continue;
+            }
+            let acceleration = well.strength / distance.max(GRAVITY_MIN_DISTANCE).powi(2);
+            movement.speed += delta / distance * acceleration * time.delta_seconds;
+        }
+    }
+}
+
+pub fn nebula_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    arena: Res<Arena>,
+    state: Res<GameState>,
+    nebulas: Query<(&Nebula, &Transform)>,
+    mut ships: Query<With<Spaceship, (Entity, &Transform, Mut<Movement>)>>,
+    in_nebulas: Query<&InNebula>,
+) {
+    if *state != GameState::Playing {
+        return;
+    }
+    for (entity, transform, mut movement) in ships.iter_mut() {
+        let mut disabled_shields = false;
+        for (nebula, nebula_transform) in nebulas.iter() {
+            let delta = arena.wrapped_delta(
+                transform.translation.truncate(),
+                nebula_transform.translation.truncate(),
+            );
+            if delta.length() < nebula.radius {
+                movement.speed = movement.speed * nebula.dampening.powf(time.delta_seconds);
+                disabled_shields |= nebula.disables_shields;
+            }
+        }
+        let was_disabled = in_nebulas.get_component::<InNebula>(entity).is_ok();
+        if disabled_shields && !was_disabled {
+            commands.insert_one(entity, InNebula);
+        } else if !disabled_shields && was_disabled {
+            commands.remove_one::<InNebula>(entity);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hazard_position_clears_the_spawn() {
+        let arena = Arena::new(Vec2::new(4000.0, 3000.0), ArenaEdge::Wrap);
+        let mut rng = StdRng::seed_from_u64(7);
+        for _ in 0..100 {
+            let position = random_hazard_position(&mut rng, &arena);
+            assert!(position.length() > HAZARD_SPAWN_CLEARANCE);
+        }
+    }
+
+    #[test]
+    fn hazard_position_in_a_small_arena_is_the_farthest_drawn() {
+        // The half-diagonal is below the clearance, no position can satisfy it.
+        let arena = Arena::new(Vec2::new(640.0, 400.0), ArenaEdge::Wrap);
+        let mut rng = StdRng::seed_from_u64(7);
+        let position = random_hazard_position(&mut rng, &arena);
+        assert!(position.x().abs() <= 320.0 && position.y().abs() <= 200.0);
+        assert!(position.length() > 0.0);
+    }
+}
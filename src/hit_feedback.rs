@@ -0,0 +1,238 @@
+use std::collections::{HashMap, HashSet};
+
+use super::*;
+
+const FLASH_DURATION: f32 = 0.1;
+const DAMAGE_TEXT_DURATION: f32 = 0.8;
+/// Rising speed of the damage numbers, in world units.
+const DAMAGE_TEXT_SPEED: f32 = 60.0;
+const DAMAGE_TEXT_SIZE: f32 = 24.0;
+const HEALTH_BAR_DURATION: f32 = 3.0;
+const HEALTH_BAR_WIDTH: f32 = 60.0;
+const HEALTH_BAR_HEIGHT: f32 = 6.0;
+/// Height of the health bar above its entity.
+const HEALTH_BAR_OFFSET: f32 = 50.0;
+
+/// Damage dealt to an entity with Armor.
+pub struct HitEvent {
+    pub entity: Entity,
+    pub position: Vec2,
+    pub damage: u32,
+    pub kind: DamageKind,
+}
+
+/// Entity material tinted for a short time, restored afterward.
+pub struct HitFlash {
+    timer: Timer,
+    original: Color,
+}
+
+/// Damage number rising from an impact.
+pub struct FloatingText {
+    position: Vec2,
+    timer: Timer,
+    color: Color,
+}
+
+/// Shows the health bar of the entity until finished.
+pub struct HealthBarTimer(pub Timer);
+
+pub struct HealthBar {
+    target: Entity,
+    fill: bool,
+}
+
+pub fn hit_feedback_system(
+    mut commands: Commands,
+    mut events: Local<EventReader<HitEvent>>,
+    hit_events: Res<Events<HitEvent>>,
+    asset_server: Res<AssetServer>,
+    (arena, windows): (Res<Arena>, Res<Windows>),
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    armors: Query<&Armor>,
+    handles: Query<&Handle<ColorMaterial>>,
+    mut flashes: Query<Mut<HitFlash>>,
+    mut health_bar_timers: Query<Mut<HealthBarTimer>>,
+) {
+    let window_size = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width() as f32, window.height() as f32),
+        None => return,
+    };
+    let mut handled = HashSet::new();
+    for event in events.iter(&hit_events) {
+        let color = event.kind.color();
+        let screen = arena.world_to_screen(event.position, window_size);
+        commands
+            .spawn(TextComponents {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: Rect {
+                        left: Val::Px(screen.x()),
+                        bottom: Val::Px(screen.y()),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                },
+                text: Text {
+                    value: event.damage.to_string(),
+                    font: asset_server.load("FiraSans-Bold.ttf"),
+                    style: TextStyle {
+                        font_size: DAMAGE_TEXT_SIZE,
+                        color,
+                    },
+                },
+                ..Default::default()
+            })
+            .with(FloatingText {
+                position: event.position,
+                timer: Timer::from_seconds(DAMAGE_TEXT_DURATION, false),
+                color,
+            });
+        // Destroyed entities are despawned at the end of the frame, nothing more to show.
+        let alive = armors
+            .get_component::<Armor>(event.entity)
+            .map_or(false, |armor| armor.life > 0);
+        if !alive || !handled.insert(event.entity) {
+            continue;
+        }
+        if let Ok(mut flash) = flashes.get_component_mut::<HitFlash>(event.entity) {
+            flash.timer.reset();
+        } else if let Ok(handle) = handles.get_component::<Handle<ColorMaterial>>(event.entity) {
+            if let Some(material) = materials.get_mut(handle) {
+                commands.insert_one(
+                    event.entity,
+                    HitFlash {
+                        timer: Timer::from_seconds(FLASH_DURATION, false),
+                        original: material.color,
+                    },
+                );
+                material.color = Color::rgb(1.0, 0.3, 0.3);
+            }
+        }
+        if let Ok(mut timer) = health_bar_timers.get_component_mut::<HealthBarTimer>(event.entity) {
+            timer.0.reset();
+        } else {
+            commands.insert_one(
+                event.entity,
+                HealthBarTimer(Timer::from_seconds(HEALTH_BAR_DURATION, false)),
+            );
+            for &fill in [false, true].iter() {
+                let color = if fill {
+                    Color::rgb(0.2, 0.9, 0.2)
+                } else {
+                    Color::rgba(0.1, 0.1, 0.1, 0.8)
+                };
+                commands
+                    .spawn(SpriteComponents {
+                        sprite: Sprite::new(Vec2::new(HEALTH_BAR_WIDTH, HEALTH_BAR_HEIGHT)),
+                        material: materials.add(color.into()),
+                        transform: Transform::from_translation(Vec3::new(
+                            event.position.x(),
+                            event.position.y(),
+                            if fill { 11.0 } else { 10.0 },
+                        )),
+                        ..Default::default()
+                    })
+                    .with(HealthBar {
+                        target: event.entity,
+                        fill,
+                    });
+            }
+        }
+    }
+}
+
+pub fn hit_flash_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut flashes: Query<(Entity, Mut<HitFlash>, &Handle<ColorMaterial>, &Armor)>,
+) {
+    for (entity, mut flash, handle, armor) in flashes.iter_mut() {
+        flash.timer.tick(time.delta_seconds);
+        // Entities without life left are being despawned.
+        if flash.timer.finished && armor.life > 0 {
+            if let Some(material) = materials.get_mut(handle) {
+                material.color = flash.original;
+            }
+            commands.remove_one::<HitFlash>(entity);
+        }
+    }
+}
+
+/// Move the damage numbers up in the world, and fade them out.
+pub fn floating_text_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    (arena, windows): (Res<Arena>, Res<Windows>),
+    mut texts: Query<(Entity, Mut<FloatingText>, Mut<Style>, Mut<Text>)>,
+) {
+    let window_size = match windows.get_primary() {
+        Some(window) => Vec2::new(window.width() as f32, window.height() as f32),
+        None => return,
+    };
+    for (entity, mut floating, mut style, mut text) in texts.iter_mut() {
+        floating.timer.tick(time.delta_seconds);
+        if floating.timer.finished {
+            commands.despawn(entity);
+            continue;
+        }
+        *floating.position.y_mut() += DAMAGE_TEXT_SPEED * time.delta_seconds;
+        let screen = arena.world_to_screen(floating.position, window_size);
+        style.position.left = Val::Px(screen.x());
+        style.position.bottom = Val::Px(screen.y());
+        let remaining = 1.0 - floating.timer.elapsed / floating.timer.duration;
+        let color = floating.color;
+        text.style.color = Color::rgba(color.r(), color.g(), color.b(), remaining);
+    }
+}
+
+/// Keep the health bars above their entity, while their timer runs.
+pub fn health_bar_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut targets: Query<(Entity, &Armor, &Transform, Mut<HealthBarTimer>)>,
+    mut bars: Query<(
+        Entity,
+        &HealthBar,
+        Mut<Transform>,
+        Mut<Sprite>,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let mut shown = HashMap::new();
+    for (entity, armor, transform, mut timer) in targets.iter_mut() {
+        timer.0.tick(time.delta_seconds);
+        if armor.life == 0 {
+            continue;
+        }
+        if timer.0.finished {
+            commands.remove_one::<HealthBarTimer>(entity);
+        } else {
+            let ratio = armor.life as f32 / armor.max_life as f32;
+            shown.insert(entity, (transform.translation.truncate(), ratio));
+        }
+    }
+    for (entity, bar, mut transform, mut sprite, handle) in bars.iter_mut() {
+        let (position, ratio) = match shown.get(&bar.target) {
+            Some(target) => *target,
+            None => {
+                commands.despawn(entity);
+                continue;
+            }
+        };
+        let mut translation = position + Vec2::new(0.0, HEALTH_BAR_OFFSET);
+        if bar.fill {
+            let width = HEALTH_BAR_WIDTH * ratio;
+            // Left aligned on the background
+            *translation.x_mut() -= (HEALTH_BAR_WIDTH - width) / 2.0;
+            sprite.size = Vec2::new(width, HEALTH_BAR_HEIGHT);
+            if let Some(material) = materials.get_mut(handle) {
+                material.color = Color::rgb(1.0 - ratio, ratio, 0.2);
+            }
+        }
+        let z = transform.translation.z();
+        transform.translation = translation.extend(z);
+    }
+}
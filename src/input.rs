@@ -1,26 +1,190 @@
-use std::{collections::HashSet, f32::consts::FRAC_PI_2};
+use std::{
+    collections::{BTreeMap, HashSet},
+    convert::TryFrom,
+};
 
 use super::*;
 use bevy::app::AppExit;
 use bevy_prototype_input_map::{InputMap, OnActionActive};
 use ncollide2d::pipeline::CollisionObjectSlabHandle;
+use serde::{Deserialize, Serialize};
 
-const ACTION_FORWARD: &str = "FORWARD";
-const ACTION_BACKWARD: &str = "BACKWARD";
-const ACTION_SHOOT_1: &str = "SHOOT_1";
-const ACTION_QUIT_APP: &str = "QUIT_APP";
-const ACTION_RCS_L: &str = "RCS_LEFT";
-const ACTION_RCS_R: &str = "RCS_RIGHT";
-
-pub fn setup_input(mut input_map: ResMut<InputMap>) {
-    input_map
-        .bind_mouse_button_pressed(MouseButton::Left, ACTION_SHOOT_1)
-        .bind_keyboard_pressed(KeyCode::Space, ACTION_SHOOT_1)
-        .bind_keyboard_pressed(KeyCode::W, ACTION_FORWARD)
-        .bind_keyboard_pressed(KeyCode::S, ACTION_BACKWARD)
-        .bind_keyboard_pressed(KeyCode::A, ACTION_RCS_L)
-        .bind_keyboard_pressed(KeyCode::D, ACTION_RCS_R)
-        .bind_keyboard_pressed(KeyCode::F4, ACTION_QUIT_APP);
+pub const ACTION_FORWARD: &str = "FORWARD";
+pub const ACTION_BACKWARD: &str = "BACKWARD";
+pub const ACTION_SHOOT_1: &str = "SHOOT_1";
+pub const ACTION_QUIT_APP: &str = "QUIT_APP";
+pub const ACTION_RCS_L: &str = "RCS_LEFT";
+pub const ACTION_RCS_R: &str = "RCS_RIGHT";
+pub const ACTION_USE_ITEM: &str = "USE_ITEM";
+pub const ACTION_NEXT_ITEM: &str = "NEXT_ITEM";
+/// Every action, in the order shown to the player.
+pub const ACTIONS: [&str; 8] = [
+    ACTION_FORWARD,
+    ACTION_BACKWARD,
+    ACTION_RCS_L,
+    ACTION_RCS_R,
+    ACTION_SHOOT_1,
+    ACTION_USE_ITEM,
+    ACTION_NEXT_ITEM,
+    ACTION_QUIT_APP,
+];
+
+/// Keys accepted in bindings, saved by their KeyCode name.
+/// SETTINGS_KEY, MUTE_KEY and Escape, which cancels a capture, are left out, and so are
+/// the keys hardcoded by the menus: Key1 to Key5 for the shop and perks, Return, Tab,
+/// Delete, the arrows, C and R for the settings.
+#[rustfmt::skip]
+const BINDABLE_KEYS: [KeyCode; 61] = [
+    KeyCode::A, KeyCode::B, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key0, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::Space, KeyCode::Back,
+    KeyCode::LShift, KeyCode::RShift, KeyCode::LControl, KeyCode::RControl,
+    KeyCode::LAlt, KeyCode::RAlt,
+    KeyCode::Insert, KeyCode::Home, KeyCode::End,
+    KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::Minus, KeyCode::Equals, KeyCode::Comma, KeyCode::Period, KeyCode::Slash,
+    KeyCode::Semicolon, KeyCode::Apostrophe, KeyCode::LBracket, KeyCode::RBracket,
+];
+const BINDABLE_MOUSE_BUTTONS: [MouseButton; 3] =
+    [MouseButton::Left, MouseButton::Right, MouseButton::Middle];
+
+/// Key or mouse button bound to an action.
+/// Saved as its name, "W" or "MouseLeft", to keep the settings file editable.
+#[derive(Serialize, Deserialize, Copy, Clone, Eq, PartialEq, Debug)]
+#[serde(into = "String", try_from = "String")]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+impl Binding {
+    pub fn name(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse{:?}", button),
+        }
+    }
+    pub fn is_bindable(&self) -> bool {
+        match self {
+            Binding::Key(key) => BINDABLE_KEYS.contains(key),
+            Binding::Mouse(button) => BINDABLE_MOUSE_BUTTONS.contains(button),
+        }
+    }
+}
+
+impl From<Binding> for String {
+    fn from(binding: Binding) -> String {
+        binding.name()
+    }
+}
+
+impl TryFrom<String> for Binding {
+    type Error = String;
+    fn try_from(name: String) -> Result<Binding, String> {
+        BINDABLE_KEYS
+            .iter()
+            .map(|&key| Binding::Key(key))
+            .chain(
+                BINDABLE_MOUSE_BUTTONS
+                    .iter()
+                    .map(|&button| Binding::Mouse(button)),
+            )
+            .find(|binding| binding.name() == name)
+            .ok_or_else(|| format!("Unknown key or mouse button {}", name))
+    }
+}
+
+/// Bindings of each action, keyed by action name.
+pub type Bindings = BTreeMap<String, Vec<Binding>>;
+
+pub fn default_bindings() -> Bindings {
+    let mut bindings = Bindings::new();
+    let mut bind = |action: &str, inputs: Vec<Binding>| {
+        bindings.insert(action.to_string(), inputs);
+    };
+    bind(
+        ACTION_SHOOT_1,
+        vec![
+            Binding::Mouse(MouseButton::Left),
+            Binding::Key(KeyCode::Space),
+        ],
+    );
+    bind(ACTION_FORWARD, vec![Binding::Key(KeyCode::W)]);
+    bind(ACTION_BACKWARD, vec![Binding::Key(KeyCode::S)]);
+    bind(ACTION_RCS_L, vec![Binding::Key(KeyCode::A)]);
+    bind(ACTION_RCS_R, vec![Binding::Key(KeyCode::D)]);
+    bind(ACTION_USE_ITEM, vec![Binding::Key(KeyCode::E)]);
+    bind(ACTION_NEXT_ITEM, vec![Binding::Key(KeyCode::Q)]);
+    bind(ACTION_QUIT_APP, vec![Binding::Key(KeyCode::F4)]);
+    bindings
+}
+
+/// Inputs bound to more than one action, with these actions.
+/// The input map keeps a single action per input, the others are silently lost.
+pub fn binding_conflicts(bindings: &Bindings) -> Vec<(Binding, Vec<String>)> {
+    let mut conflicts: Vec<(Binding, Vec<String>)> = Vec::new();
+    for (action, inputs) in bindings.iter() {
+        for &input in inputs.iter() {
+            match conflicts.iter_mut().find(|(binding, _)| *binding == input) {
+                Some((_, actions)) => actions.push(action.clone()),
+                None => conflicts.push((input, vec![action.clone()])),
+            }
+        }
+    }
+    conflicts.retain(|(_, actions)| actions.len() > 1);
+    conflicts
+}
+
+/// Replace every binding of the input map.
+pub fn apply_bindings(input_map: &mut InputMap, bindings: &Bindings) {
+    *input_map = InputMap::default();
+    for (action, inputs) in bindings.iter() {
+        for input in inputs.iter() {
+            match *input {
+                Binding::Key(key) => input_map.bind_keyboard_pressed(key, action),
+                Binding::Mouse(button) => input_map.bind_mouse_button_pressed(button, action),
+            };
+        }
+    }
+}
+
+/// How the keys move the ship.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ControlScheme {
+    /// The ship faces the cursor, keys thrust and strafe relative to the ship.
+    CursorAim,
+    /// Asteroids like, left and right rotate the ship, no strafing.
+    Classic,
+    /// Keys move along the screen axes, the ship still faces the cursor.
+    ScreenRelative,
+}
+
+impl ControlScheme {
+    pub const ALL: [ControlScheme; 3] = [
+        ControlScheme::CursorAim,
+        ControlScheme::Classic,
+        ControlScheme::ScreenRelative,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            ControlScheme::CursorAim => "Cursor aim",
+            ControlScheme::Classic => "Classic rotate and thrust",
+            ControlScheme::ScreenRelative => "Screen relative",
+        }
+    }
+    pub fn next(&self) -> ControlScheme {
+        let index = ControlScheme::ALL.iter().position(|s| s == self).unwrap();
+        ControlScheme::ALL[(index + 1) % ControlScheme::ALL.len()]
+    }
+}
+
+pub fn setup_input(mut input_map: ResMut<InputMap>, settings: Res<Settings>) {
+    apply_bindings(&mut input_map, &settings.bindings);
 }
 #[derive(Default)]
 pub struct ActionSystemState {
@@ -74,23 +238,68 @@ pub fn cursor_collider_system(
         na::zero(),
     ));
 }
-/// Update User ship orientation based on mouse position.
+/// Turn the ships toward the cursor, or the right stick direction with a gamepad.
 pub fn orientation_system(
+    time: Res<Time>,
+    state: Res<GameState>,
     cursor_world_pos: Res<Cursor2dWorldPos>,
-    mut query_spaceship: Query<With<UserControlled, Mut<Transform>>>,
+    (gamepad_state, settings): (Res<GamepadState>, Res<Settings>),
+    mut query_spaceship: Query<
+        With<UserControlled, (&InputDevice, &Spaceship, &Transform, Mut<Movement>)>,
+    >,
 ) {
-    for mut ship_transform in query_spaceship.iter_mut() {
-        let world_x = cursor_world_pos.world_pos.x();
-        let world_y = cursor_world_pos.world_pos.y();
-        let ship_x = ship_transform.translation.x();
-        let ship_y = ship_transform.translation.y();
-        ship_transform.rotation = Quat::from_rotation_z((world_y - ship_y).atan2(world_x - ship_x));
+    if *state != GameState::Playing {
+        return;
+    }
+    for (device, ship, ship_transform, mut movement) in query_spaceship.iter_mut() {
+        if *device == InputDevice::Remote {
+            continue;
+        }
+        let angle = rotation_angle(&ship_transform.rotation);
+        let target_angle = match device.uses_gamepad(&gamepad_state) {
+            // Turning is done by the keys in action_system.
+            false if settings.control_scheme == ControlScheme::Classic => continue,
+            true => {
+                let aim = gamepad_state.right_stick;
+                if aim == Vec2::zero() {
+                    // Stick released, hold the current heading.
+                    angle
+                } else {
+                    aim.y().atan2(aim.x())
+                }
+            }
+            false => {
+                let world_x = cursor_world_pos.world_pos.x();
+                let world_y = cursor_world_pos.world_pos.y();
+                let ship_x = ship_transform.translation.x();
+                let ship_y = ship_transform.translation.y();
+                (world_y - ship_y).atan2(world_x - ship_x)
+            }
+        };
+        movement.angvel = ship.steer(movement.angvel, angle, target_angle, time.delta_seconds);
+    }
+}
+
+/// Send a FireWeaponEvent if the weapon is ready.
+pub fn fire_weapon(
+    ship_entity: Entity,
+    weapon: &mut Weapon,
+    fire_weapon_events: &mut Events<FireWeaponEvent>,
+) {
+    if weapon.fire_timer.finished {
+        fire_weapon_events.send(FireWeaponEvent {
+            ship_entity,
+            munition_lifespan: weapon.munition_lifespan,
+            damage: weapon.damage,
+        });
+        weapon.fire_timer.reset();
     }
 }
 
 pub fn action_system(
     mut state: Local<ActionSystemState>,
     time: Res<Time>,
+    (game_state, settings): (Res<GameState>, Res<Settings>),
     action_active_events: Res<Events<OnActionActive>>,
     mut app_exit_events: ResMut<Events<AppExit>>,
     mut fire_weapon_events: ResMut<Events<FireWeaponEvent>>,
@@ -99,58 +308,88 @@ pub fn action_system(
             UserControlled,
             (
                 Entity,
+                &InputDevice,
                 &Spaceship,
                 Mut<Movement>,
-                Mut<Transform>,
+                &Transform,
                 Mut<Weapon>,
             ),
         >,
     >,
+    mut emitters: Query<Mut<ParticleEmitter>>,
 ) {
-    for active_event in state.active_reader.iter(&action_active_events) {
-        if active_event.action == ACTION_QUIT_APP {
-            app_exit_events.send(AppExit);
-        } else {
-            for (ship_entity, ship, mut movement, ship_transform, mut weapon) in
-                query_spaceship.iter_mut()
-            {
-                if active_event.action == ACTION_SHOOT_1 {
-                    if weapon.fire_timer.finished {
-                        fire_weapon_events.send(FireWeaponEvent {
-                            ship_entity: ship_entity,
-                            munition_lifespan: weapon.munition_lifespan,
-                        });
-                        weapon.fire_timer.reset();
-                    }
+    let active: HashSet<String> = state
+        .active_reader
+        .iter(&action_active_events)
+        .map(|active_event| active_event.action.clone())
+        .collect();
+    // Keys are being rebound in the settings, quitting would be surprising.
+    if active.contains(ACTION_QUIT_APP) && *game_state != GameState::Settings {
+        app_exit_events.send(AppExit);
+    }
+    if *game_state != GameState::Playing {
+        return;
+    }
+    let is_active = |action: &str| active.contains(action);
+    let dt = time.delta_seconds;
+    for (ship_entity, device, ship, mut movement, ship_transform, mut weapon) in
+        query_spaceship.iter_mut()
+    {
+        // The second co-op player only has the gamepad.
+        if matches!(device, InputDevice::Gamepad | InputDevice::Remote) {
+            continue;
+        }
+        if is_active(ACTION_SHOOT_1) {
+            fire_weapon(ship_entity, &mut weapon, &mut fire_weapon_events);
+        }
+        let forward = (ship_transform.rotation * Vec3::unit_x()).truncate();
+        let left = Vec2::new(-forward.y(), forward.x());
+        let thrusting = match settings.control_scheme {
+            ControlScheme::CursorAim | ControlScheme::Classic => {
+                if is_active(ACTION_FORWARD) {
+                    movement.speed += forward * ship.max_linvel * dt;
                 }
-                if active_event.action == ACTION_FORWARD {
-                    movement.speed += (ship_transform.rotation
-                        * (Vec3::unit_x() * ship.max_linvel)
-                        * time.delta_seconds)
-                        .truncate();
+                if is_active(ACTION_BACKWARD) {
+                    movement.speed -= forward * ship.max_linvel * dt;
                 }
-                if active_event.action == ACTION_BACKWARD {
-                    movement.speed -= (ship_transform.rotation
-                        * (Vec3::unit_x() * ship.max_linvel)
-                        * time.delta_seconds)
-                        .truncate();
+                if settings.control_scheme == ControlScheme::Classic {
+                    // Rotate while held, orientation_system does not steer in this scheme.
+                    let turn = is_active(ACTION_RCS_L) as i32 - is_active(ACTION_RCS_R) as i32;
+                    let wanted_angvel = turn as f32 * ship.max_angvel;
+                    movement.angvel = ship.accelerate_rotation(movement.angvel, wanted_angvel, dt);
+                } else {
+                    if is_active(ACTION_RCS_L) {
+                        movement.speed += left * ship.max_latvel * dt;
+                    }
+                    if is_active(ACTION_RCS_R) {
+                        movement.speed -= left * ship.max_latvel * dt;
+                    }
                 }
-                if active_event.action == ACTION_RCS_L {
-                    movement.speed += (ship_transform.rotation
-                        * Quat::from_rotation_z(FRAC_PI_2)
-                        * Vec3::unit_x()
-                        * ship.max_latvel
-                        * time.delta_seconds)
-                        .truncate();
+                is_active(ACTION_FORWARD)
+            }
+            ControlScheme::ScreenRelative => {
+                let mut direction = Vec2::zero();
+                for &(action, axis) in [
+                    (ACTION_FORWARD, Vec2::unit_y()),
+                    (ACTION_BACKWARD, -Vec2::unit_y()),
+                    (ACTION_RCS_L, -Vec2::unit_x()),
+                    (ACTION_RCS_R, Vec2::unit_x()),
+                ]
+                .iter()
+                {
+                    if is_active(action) {
+                        direction += axis;
+                    }
                 }
-                if active_event.action == ACTION_RCS_R {
-                    movement.speed += (ship_transform.rotation
-                        * Quat::from_rotation_z(-FRAC_PI_2)
-                        * Vec3::unit_x()
-                        * ship.max_latvel
-                        * time.delta_seconds)
-                        .truncate();
+                if direction != Vec2::zero() {
+                    movement.speed += direction.normalize() * ship.max_linvel * dt;
                 }
+                direction.dot(forward) > 0.0
+            }
+        };
+        if thrusting {
+            if let Ok(mut emitter) = emitters.get_component_mut::<ParticleEmitter>(ship_entity) {
+                emitter.active = true;
             }
         }
     }
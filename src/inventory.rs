@@ -0,0 +1,224 @@
+use std::collections::{HashMap, HashSet};
+
+use super::*;
+use bevy_prototype_input_map::OnActionActive;
+
+const INVENTORY_CAPACITY: usize = 6;
+
+/// Per-ship storage for loot that is not consumed on pickup.
+pub struct Inventory {
+    pub credits: u32,
+    pub resources: HashMap<ResourceKind, u32>,
+    /// Boosts waiting to be used.
+    pub items: Vec<Loot>,
+    /// Index in `items` of the boost used by ACTION_USE_ITEM.
+    pub selected: usize,
+    pub capacity: usize,
+}
+
+impl Inventory {
+    pub fn new() -> Inventory {
+        Inventory {
+            credits: 0,
+            resources: HashMap::new(),
+            items: Vec::new(),
+            selected: 0,
+            capacity: INVENTORY_CAPACITY,
+        }
+    }
+    /// Store the loot, returns false if there is no room left for it.
+    pub fn store(&mut self, loot: Loot) -> bool {
+        match loot {
+            Loot::Credits(n) => self.credits += n,
+            Loot::Resource(kind, n) => *self.resources.entry(kind).or_insert(0) += n,
+            Loot::None => {}
+            _ => {
+                if self.items.len() >= self.capacity {
+                    return false;
+                }
+                self.items.push(loot);
+            }
+        }
+        true
+    }
+    /// Remove the credits, returns false if there is not enough.
+    pub fn spend(&mut self, credits: u32) -> bool {
+        if self.credits < credits {
+            return false;
+        }
+        self.credits -= credits;
+        true
+    }
+    pub fn resource(&self, kind: ResourceKind) -> u32 {
+        *self.resources.get(&kind).unwrap_or(&0)
+    }
+    pub fn select_next(&mut self) {
+        if !self.items.is_empty() {
+            self.selected = (self.selected + 1) % self.items.len();
+        }
+    }
+    pub fn take_selected(&mut self) -> Option<Loot> {
+        if self.items.is_empty() {
+            return None;
+        }
+        let loot = self.items.remove(self.selected);
+        if self.selected >= self.items.len() {
+            self.selected = 0;
+        }
+        Some(loot)
+    }
+}
+
+#[derive(Default)]
+pub struct InventoryActionState {
+    active_reader: EventReader<OnActionActive>,
+    /// Actions active last frame, inventory actions trigger only once per press.
+    was_active: HashSet<String>,
+}
+
+/// Use or cycle the items of each ship with its own device, actions on the keyboard,
+/// GAMEPAD_USE_ITEM and GAMEPAD_NEXT_ITEM on the gamepad.
+pub fn inventory_action_system(
+    mut state: Local<InventoryActionState>,
+    action_active_events: Res<Events<OnActionActive>>,
+    (gamepad_state, gamepad_buttons): (Res<GamepadState>, Res<Input<GamepadButton>>),
+    mut query: Query<With<UserControlled, (&InputDevice, Mut<Inventory>, Mut<Weapon>)>>,
+) {
+    let mut active = HashSet::new();
+    for active_event in state.active_reader.iter(&action_active_events) {
+        active.insert(active_event.action.clone());
+    }
+    let action_pressed =
+        |action: &str| active.contains(action) && !state.was_active.contains(action);
+    // (next item, use item) of each device
+    let keyboard = (
+        action_pressed(ACTION_NEXT_ITEM),
+        action_pressed(ACTION_USE_ITEM),
+    );
+    let gamepad = (
+        gamepad_state.just_pressed(&gamepad_buttons, GAMEPAD_NEXT_ITEM),
+        gamepad_state.just_pressed(&gamepad_buttons, GAMEPAD_USE_ITEM),
+    );
+    for (device, mut inventory, mut weapon) in query.iter_mut() {
+        let (next_item, use_item) = match device {
+            InputDevice::Auto => (keyboard.0 || gamepad.0, keyboard.1 || gamepad.1),
+            InputDevice::KeyboardMouse => keyboard,
+            InputDevice::Gamepad => gamepad,
+            InputDevice::Remote => (false, false),
+        };
+        if next_item {
+            inventory.select_next();
+        }
+        if use_item {
+            if let Some(loot) = inventory.take_selected() {
+                loot.apply(&mut weapon);
+            }
+        }
+    }
+    state.was_active = active;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum InventoryText {
+    /// Name of the player, in co-op only.
+    Player,
+    Credits,
+    Resources,
+    Items,
+}
+
+/// Spawn one inventory panel per local player, in their corner of the screen.
+pub fn setup_inventory_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    local_players: Res<LocalPlayers>,
+) {
+    let font = asset_server.load("FiraSans-Bold.ttf");
+    let mut lines = vec![
+        InventoryText::Credits,
+        InventoryText::Resources,
+        InventoryText::Items,
+    ];
+    if local_players.0 > 1 {
+        lines.insert(0, InventoryText::Player);
+    }
+    for player in (0..local_players.0).map(LocalPlayer) {
+        commands
+            .spawn(NodeComponents {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: hud_position(player, 10.0),
+                    flex_direction: FlexDirection::ColumnReverse,
+                    align_items: if player.0 == 0 {
+                        AlignItems::FlexStart
+                    } else {
+                        AlignItems::FlexEnd
+                    },
+                    ..Default::default()
+                },
+                material: materials.add(Color::NONE.into()),
+                ..Default::default()
+            })
+            .with_children(|parent| {
+                for &line in lines.iter() {
+                    parent
+                        .spawn(TextComponents {
+                            text: Text {
+                                value: "".to_string(),
+                                font: font.clone(),
+                                style: TextStyle {
+                                    font_size: 24.0,
+                                    color: Color::rgb(0.8, 0.8, 0.9),
+                                },
+                            },
+                            ..Default::default()
+                        })
+                        .with(line)
+                        .with(player);
+                }
+            });
+    }
+}
+
+pub fn inventory_ui_system(
+    inventories: Query<With<UserControlled, (&LocalPlayer, Changed<Inventory>)>>,
+    mut texts: Query<(&LocalPlayer, &InventoryText, Mut<Text>)>,
+) {
+    for (player, inventory) in inventories.iter() {
+        for (text_player, line, mut text) in texts.iter_mut() {
+            if text_player != player {
+                continue;
+            }
+            text.value = match line {
+                InventoryText::Player => format!("Player {}", player.0 + 1),
+                InventoryText::Credits => format!("Credits: {}", inventory.credits),
+                InventoryText::Resources => format!(
+                    "Ore: {}  Crystal: {}",
+                    inventory.resource(ResourceKind::Ore),
+                    inventory.resource(ResourceKind::Crystal)
+                ),
+                InventoryText::Items => {
+                    let items = inventory
+                        .items
+                        .iter()
+                        .enumerate()
+                        .map(|(i, loot)| {
+                            if i == inventory.selected {
+                                format!("[{}]", loot.name())
+                            } else {
+                                loot.name().to_string()
+                            }
+                        })
+                        .collect::<Vec<_>>();
+                    format!(
+                        "Items {}/{}: {}",
+                        inventory.items.len(),
+                        inventory.capacity,
+                        items.join(" ")
+                    )
+                }
+            };
+        }
+    }
+}
@@ -3,12 +3,33 @@ use bevy_contrib_bobox::{OutlineConfiguration, OutlineMaterial};
 use super::*;
 pub struct LootEvent {
     pub position: Vec2,
+    /// Credits always dropped, on top of the random loot.
+    pub credits: u32,
+    /// Ship credited with the kill, the loot is reserved for it for a while.
+    pub owner: Option<Entity>,
+}
+
+/// Seconds during which only the owner can pick up the loot.
+const LOOT_RESERVATION: f32 = 3.0;
+
+/// Loot reserved to the ship which destroyed the enemy.
+pub struct LootOwner {
+    pub owner: Entity,
+    pub reserved: Timer,
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum ResourceKind {
+    Ore,
+    Crystal,
 }
 
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
 pub enum Loot {
     IncreasedRateOfFire(u32),
     IncreasedMunitionDuration(u32),
+    Credits(u32),
+    Resource(ResourceKind, u32),
     None,
 }
 impl Loot {
@@ -16,9 +37,50 @@ impl Loot {
         match &self {
             Loot::IncreasedRateOfFire(_) => String::from("bolt_silver.png"),
             Loot::IncreasedMunitionDuration(_) => String::from("bolt_bronze.png"),
+            Loot::Credits(_) => String::from("sprite_sphere_256x256.png"),
+            Loot::Resource(ResourceKind::Ore, _) => String::from("spaceMeteors_001.png"),
+            Loot::Resource(ResourceKind::Crystal, _) => String::from("sprite_sphere_256x256.png"),
             Loot::None => panic!("Can't get asset for Loot::None"),
         }
     }
+    pub fn get_color(&self) -> Color {
+        match &self {
+            Loot::Credits(_) => Color::rgb(1.0, 0.85, 0.2),
+            Loot::Resource(ResourceKind::Ore, _) => Color::rgb(0.9, 0.5, 0.2),
+            Loot::Resource(ResourceKind::Crystal, _) => Color::rgb(0.3, 0.9, 1.0),
+            _ => Color::WHITE,
+        }
+    }
+    /// Scale factor bringing the asset to the size of a bolt (30px).
+    fn get_scale(&self) -> f32 {
+        match &self {
+            Loot::Credits(_) | Loot::Resource(ResourceKind::Crystal, _) => 30.0 / 256.0,
+            Loot::Resource(ResourceKind::Ore, _) => 30.0 / 215.0,
+            _ => 1.0,
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match &self {
+            Loot::IncreasedRateOfFire(_) => "Rate of fire",
+            Loot::IncreasedMunitionDuration(_) => "Munition duration",
+            Loot::Credits(_) => "Credits",
+            Loot::Resource(ResourceKind::Ore, _) => "Ore",
+            Loot::Resource(ResourceKind::Crystal, _) => "Crystal",
+            Loot::None => "None",
+        }
+    }
+    /// Apply a boost loot to the weapon, other loots have no direct effect.
+    pub fn apply(&self, weapon: &mut Weapon) {
+        match &self {
+            Loot::IncreasedRateOfFire(p) => {
+                weapon.fire_timer.duration = weapon.fire_timer.duration / (*p as f32 / 100.0);
+            }
+            Loot::IncreasedMunitionDuration(p) => {
+                weapon.munition_lifespan = weapon.munition_lifespan * (*p as f32 / 100.0);
+            }
+            _ => {}
+        }
+    }
 }
 
 pub fn loot_spawn_system(
@@ -32,25 +94,45 @@ pub fn loot_spawn_system(
 ) {
     for event in loot_event_reader.iter(&*loot_events) {
         let mut rng = thread_rng();
-        let loot = match rng.gen_range(0, 3) {
+        let loot = match rng.gen_range(0, 5) {
             0 => Loot::IncreasedRateOfFire(200),
             1 => Loot::IncreasedMunitionDuration(150),
+            2 => Loot::Resource(ResourceKind::Ore, rng.gen_range(1, 4)),
+            3 => Loot::Resource(ResourceKind::Crystal, 1),
             _ => Loot::None,
         };
+        let mut drops = Vec::new();
+        if event.credits > 0 {
+            drops.push((Loot::Credits(event.credits), event.position));
+        }
         if loot != Loot::None {
+            // Shift it so both drops can be told apart.
+            let angle = rng.gen_range(0.0, 2.0 * PI);
+            let offset = Vec2::new(angle.cos(), angle.sin()) * 30.0;
+            drops.push((loot, event.position + offset));
+        }
+        for (loot, position) in drops {
+            let scale = loot.get_scale();
             commands
                 .spawn_with_ghosts(SpriteComponents {
                     transform: Transform {
-                        translation: Vec3::new(event.position.x(), event.position.y(), -0.2),
-                        scale: Vec3::splat(0.5),
+                        translation: Vec3::new(position.x(), position.y(), -0.2),
+                        scale: Vec3::splat(0.5 * scale),
                         ..Default::default()
                     },
-                    material: materials.add(asset_server.load(loot.get_asset().as_str()).into()),
+                    material: materials.add(ColorMaterial::modulated_texture(
+                        asset_server.load(loot.get_asset().as_str()),
+                        loot.get_color(),
+                    )),
                     ..Default::default()
                 })
                 .with(loot)
                 .with(ColliderType::Loot)
-                .with(TweenScale::new(Vec3::splat(0.4), Vec3::splat(0.75), 1.0))
+                .with(TweenScale::new(
+                    Vec3::splat(0.4 * scale),
+                    Vec3::splat(0.75 * scale),
+                    1.0,
+                ))
                 .with(outline_materials.add(OutlineMaterial {
                     configuration: OutlineConfiguration {
                         color: Color::rgb(0.7, 0.7, 1.0),
@@ -62,16 +144,40 @@ pub fn loot_spawn_system(
             let entity = commands.current_entity().unwrap();
             let shape = ShapeHandle::new(Ball::new(30.0 * 0.75 * 0.5));
             let (collision_object_handle, _) = collide_world.add(
-                Isometry2::new(
-                    Vector2::new(event.position.x(), event.position.y()),
-                    na::zero(),
-                ),
+                Isometry2::new(Vector2::new(position.x(), position.y()), na::zero()),
                 shape,
                 collide_groups.loots,
                 GeometricQueryType::Contacts(0.0, 0.0),
                 entity,
             );
             commands.insert(entity, (collision_object_handle,));
+            if let Some(owner) = event.owner {
+                commands.insert_one(
+                    entity,
+                    LootOwner {
+                        owner,
+                        reserved: Timer::from_seconds(LOOT_RESERVATION, false),
+                    },
+                );
+            }
+        }
+    }
+}
+
+/// Release reserved loot to every ship once the reservation is over.
+pub fn loot_owner_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    state: Res<GameState>,
+    mut query: Query<(Entity, Mut<LootOwner>)>,
+) {
+    if *state != GameState::Playing {
+        return;
+    }
+    for (entity, mut loot_owner) in query.iter_mut() {
+        loot_owner.reserved.tick(time.delta_seconds);
+        if loot_owner.reserved.finished {
+            commands.remove_one::<LootOwner>(entity);
         }
     }
 }
@@ -14,27 +14,75 @@ use std::f32::consts::PI;
 const CAMERA_SCALE: f32 = 1.0;
 const WINDOW_WIDTH: u32 = 1280;
 const WINDOW_HEIGHT: u32 = 800;
+/// Asteroids of a wave are spawned away from the ships.
+const ASTEROID_SPAWN_CLEARANCE: f32 = 300.0;
+/// Positions drawn before settling for the farthest one, in an arena too small for the clearance.
+const ASTEROID_SPAWN_ATTEMPTS: u32 = 32;
 
 mod arena;
 mod armor;
+mod audio;
+mod camera;
 mod collision;
+mod game_over;
+mod gamepad;
+mod hazard;
+mod hit_feedback;
 mod input;
+mod inventory;
 mod loot;
+mod music;
+mod net;
+mod particle;
+mod perk;
+mod physics;
+mod profile;
+mod progression;
+mod radar;
+mod rollback;
+mod score;
 mod selection;
+mod settings;
+mod shop;
 mod spaceship;
+mod starfield;
 mod ui;
+mod wave;
 mod weapon;
 use arena::*;
 use armor::*;
+use audio::*;
+use camera::*;
 use collision::*;
+use game_over::*;
+use gamepad::*;
+use hazard::*;
+use hit_feedback::*;
 use input::*;
+use inventory::*;
 use loot::*;
+use music::*;
+use net::*;
+use particle::*;
+use perk::*;
+use physics::*;
+use profile::*;
+use progression::*;
+use radar::*;
+use rollback::*;
+use score::*;
 use selection::*;
+use settings::*;
+use shop::*;
 use spaceship::*;
+use starfield::*;
 use ui::*;
+use wave::*;
 use weapon::*;
 
 fn main() {
+    let mut profile = Profile::load();
+    profile.select_from_args();
     App::build()
         .add_resource(ClearColor(Color::rgb_u8(5, 5, 10)))
         .add_resource(WindowDescriptor {
@@ -43,10 +91,36 @@ fn main() {
             height: WINDOW_HEIGHT,
             ..Default::default()
         })
+        .add_resource(GameState::Playing)
+        .add_resource(GameSeed::from_args())
+        .add_resource(GameMode::from_args())
+        .add_resource(LocalPlayers::from_args())
+        .add_resource(Background::from_args())
+        .add_resource(Starfield::default())
+        .add_resource(ParticleBudget::default())
+        .add_resource(Wave::new())
+        .add_resource(PerkChoice::default())
+        .add_resource(ShopCursor::default())
+        .add_resource(XpCurve::default())
+        .add_resource(Network::from_args(&profile))
+        .add_resource(profile)
+        .add_resource(Settings::load())
+        .add_resource(SettingsMenu::default())
+        .add_resource(GamepadState::default())
+        .add_resource(MusicState::default())
+        .add_resource(RunStats::default())
+        .add_resource(HighScores::load())
+        .add_resource(NameEntry::default())
         .add_event::<XpEvent>()
+        .add_event::<LevelUpEvent>()
         .add_event::<LootEvent>()
         .add_event::<CursorSelectionEvent>()
         .add_event::<CollisionEvent>()
+        .add_event::<ImpactEvent>()
+        .add_event::<CameraShakeEvent>()
+        .add_event::<ParticleEvent>()
+        .add_event::<HitEvent>()
+        .add_event::<SoundEvent>()
         .add_plugins(DefaultPlugins)
         .add_plugin(bevy_contrib_bobox::Cursor2dWorldPosPlugin)
         .add_plugin(bevy_contrib_bobox::Outline2dPlugin)
@@ -58,157 +132,311 @@ fn main() {
         //.add_startup_system(spawn_background.system())
         .add_startup_system(spawn_cursor_collider.system())
         .add_startup_system(setup_ui.system())
+        .add_startup_system(setup_inventory_ui.system())
+        .add_startup_system(setup_progression_ui.system())
+        .add_startup_system(setup_versus_ui.system())
+        .add_startup_system(setup_sounds.system())
+        .add_startup_system(setup_audio_mixer.thread_local_system())
         .add_startup_system_to_stage(startup_stage::POST_STARTUP, spawn_player_spaceship.system())
         .add_startup_system_to_stage(startup_stage::POST_STARTUP, spawn_arena_markers.system())
+        .add_startup_system_to_stage(startup_stage::POST_STARTUP, spawn_starfield.system())
+        .add_startup_system_to_stage(startup_stage::POST_STARTUP, spawn_hazards.system())
+        .add_startup_system_to_stage(startup_stage::POST_STARTUP, spawn_arena_edges.system())
+        .add_startup_system_to_stage(startup_stage::POST_STARTUP, setup_radar.system())
+        .add_startup_system_to_stage(
+            startup_stage::POST_STARTUP,
+            setup_music.thread_local_system(),
+        )
         .add_system(spawn_asteroid.system())
+        .add_system(gamepad_input_system.system())
         .add_system(action_system.system())
+        .add_system(gamepad_action_system.system())
+        .add_system(net_server_system.system())
+        .add_system(net_input_system.system())
+        .add_system(versus_system.system())
+        .add_system(remote_input_system.system())
+        .add_system(particle_emitter_system.system())
         .add_system(fire_weapon_system.system())
+        .add_system(gravity_system.system())
+        .add_system(nebula_system.system())
         .add_system(position_system.system())
+        .add_system(arena_shrink_system.system())
+        .add_system(arena_edge_sprite_system.system())
+        .add_system(net_client_system.system())
+        .add_system(versus_render_system.system())
+        .add_system(versus_ui_system.system())
         .add_system(camera_follow_system.system())
         .add_system(orientation_system.system())
         .add_system(collide_position_system.system())
         .add_system(collision_system.system())
         .add_system(collision_event_system.system())
-        .add_system(spriteghost_quadrant_system.system()) // After camera_follow to catch Arena.shown mutations
+        .add_system(impact_system.system())
+        .add_system(arena_view_system.system()) // After camera_follow to catch camera moves
+        .add_system(spriteghost_count_system.system())
+        .add_system(spriteghost_position_system.system())
         .add_system(spriteghost_sync_system.system())
+        .add_system(starfield_system.system())
+        .add_system(net_snapshot_system.system())
+        .add_system(net_loopback_system.system())
         .add_system(lifespan_system.system())
         .add_system(weapon_system.system())
         .add_system(xp_system.system())
         .add_system(loot_spawn_system.system())
+        .add_system(loot_owner_system.system())
         .add_system(tweenscale_system.system())
+        .add_system(particle_spawn_system.system())
+        .add_system(particle_system.system())
+        .add_system(hit_feedback_system.system())
+        .add_system(hit_flash_system.system())
+        .add_system(floating_text_system.system())
+        .add_system(health_bar_system.system())
         .add_system(cursor_collider_system.system())
         .add_system(show_selection_system.system())
+        .add_system(inventory_action_system.system())
+        .add_system(inventory_ui_system.system())
+        .add_system(progression_ui_system.system())
+        .add_system(wave_system.system())
+        .add_system(shop_system.system())
+        .add_system(shop_ui_system.system())
+        .add_system(level_up_system.system())
+        .add_system(perk_choice_system.system())
+        .add_system(perk_ui_system.system())
+        .add_system(invulnerability_system.system())
+        .add_system(profile_game_over_system.system())
+        .add_system(score_wave_system.system())
+        .add_system(name_entry_system.system())
+        .add_system(game_over_ui_system.system())
+        .add_system(settings_system.system())
+        .add_system(settings_ui_system.system())
+        .add_system(mute_system.system())
+        .add_system(audio_mixer_system.thread_local_system())
+        .add_system(music_intensity_system.system())
+        .add_system(music_system.thread_local_system())
+        .add_system(radar_system.system())
+        .add_system(offscreen_indicator_system.system())
         .run();
 }
 
 pub struct FollowedCamera(Entity);
 
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum GameState {
+    Playing,
+    /// Between two waves, the shop is shown.
+    Shop,
+    /// A perk is to be chosen after a level up.
+    LevelUp,
+    /// The player ship is destroyed.
+    GameOver,
+    /// The game is paused to change the settings.
+    Settings,
+}
+
 #[derive(Debug)]
 pub struct Movement {
     pub speed: Vec2,
     /// Defines speed factor after 1s
     pub dampening: f32,
+    /// Angular velocity around Z, in rad/s
+    pub angvel: f32,
+    /// Used for collision impulses, 0.0 for an immovable body
+    pub mass: f32,
 }
 pub struct UserControlled {}
 pub struct Enemy {
     pub xp: u32,
 }
-pub fn setup(mut commands: Commands) {
-    commands.spawn(Camera2dComponents {
-        transform: Transform::from_scale(Vec3::new(CAMERA_SCALE, CAMERA_SCALE, CAMERA_SCALE)),
-        ..Default::default()
-    });
-    commands.spawn(UiCameraComponents::default());
-    commands.insert_resource(Arena {
-        size: Vec2::new(2.0 * (WINDOW_WIDTH as f32), 2.0 * (WINDOW_HEIGHT as f32)),
-        shown: ArenaQuadrant::NW,
-    });
+/// Tag component for enemies counted in the current wave.
+pub struct WaveMember;
+/// Value following the flag on the command line.
+pub fn arg_value(flag: &str) -> Option<String> {
+    let mut args = std::env::args().skip_while(|arg| arg != flag);
+    args.next();
+    args.next()
 }
-pub fn _spawn_background(
-    mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut materials: ResMut<Assets<ColorMaterial>>,
-) {
-    commands.spawn_with_ghosts(SpriteComponents {
-        material: materials.add(
-            asset_server
-                .load("pexels-francesco-ungaro-998641.png")
-                .into(),
-        ),
-        //sprite: Sprite::new(Vec2::new(1280.0, 853.0)),
-        //material: materials.add(Color::rgb(0.2, 0.2, 0.2).into()),
-        transform: Transform {
-            translation: Vec3::new(0.0, 0.0, -10.0),
-            scale: Vec3::new(CAMERA_SCALE, CAMERA_SCALE, CAMERA_SCALE),
+/// Selected with --mode, defines the arena edges.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum GameMode {
+    Classic,
+    Pinball,
+    Cage,
+    BattleRoyale,
+}
+impl GameMode {
+    pub fn from_args() -> GameMode {
+        let mode = arg_value("--mode");
+        match mode.as_deref() {
+            None | Some("classic") => GameMode::Classic,
+            Some("pinball") => GameMode::Pinball,
+            Some("cage") => GameMode::Cage,
+            Some("battle-royale") => GameMode::BattleRoyale,
+            Some(mode) => {
+                println!(
+                    "Unknown mode {}, expected classic, pinball, cage or battle-royale",
+                    mode
+                );
+                GameMode::Classic
+            }
+        }
+    }
+    pub fn arena_edge(&self) -> ArenaEdge {
+        match self {
+            GameMode::Classic => ArenaEdge::Wrap,
+            GameMode::Pinball => ArenaEdge::Bounce,
+            GameMode::Cage => ArenaEdge::Walls,
+            GameMode::BattleRoyale => ArenaEdge::Shrinking,
+        }
+    }
+}
+/// Seed of the procedural generation, random unless given with --seed.
+pub struct GameSeed(pub u64);
+impl GameSeed {
+    pub fn from_args() -> GameSeed {
+        let seed = arg_value("--seed")
+            .and_then(|seed| seed.parse().ok())
+            .unwrap_or_else(|| thread_rng().gen());
+        GameSeed(seed)
+    }
+}
+/// Players sharing the screen, the second one uses a gamepad.
+pub struct LocalPlayers(pub usize);
+impl LocalPlayers {
+    pub fn from_args() -> LocalPlayers {
+        let players = arg_value("--players");
+        match players.as_deref().map(str::parse) {
+            None => LocalPlayers(1),
+            Some(Ok(players @ 1..=2)) => LocalPlayers(players),
+            Some(_) => {
+                println!("Unknown players {:?}, expected 1 or 2", players);
+                LocalPlayers(1)
+            }
+        }
+    }
+}
+/// Index of the local player owning a ship, or a HUD text showing that ship.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub struct LocalPlayer(pub usize);
+pub fn setup(mut commands: Commands, game_mode: Res<GameMode>) {
+    commands
+        .spawn(Camera2dComponents {
+            transform: Transform::from_scale(Vec3::new(CAMERA_SCALE, CAMERA_SCALE, CAMERA_SCALE)),
             ..Default::default()
-        },
-        ..Default::default()
-    });
+        })
+        .with(CameraRig::default());
+    commands.spawn(UiCameraComponents::default());
+    commands.insert_resource(Arena::new(Arena::size_from_args(), game_mode.arena_edge()));
 }
 pub fn spawn_asteroid(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     arena: Res<Arena>,
+    state: Res<GameState>,
+    mut wave: ResMut<Wave>,
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut outline_materials: ResMut<Assets<OutlineMaterial>>,
     mut collide_world: ResMut<CollisionWorld<f32, Entity>>,
-    collide_groups: Res<CollideGroups>,
-    enemies: Query<&Enemy>,
+    (collide_groups, network): (Res<CollideGroups>, Res<Network>),
     ship_transforms: Query<With<Spaceship, &Transform>>,
 ) {
-    let n_enemies = enemies.iter().len();
-    if n_enemies < 1 {
-        // Find a far enough position
+    // Enemies are only spawned where the waves are simulated.
+    if *state != GameState::Playing || !network.simulates_waves() {
+        return;
+    }
+    while wave.to_spawn > 0 {
+        wave.to_spawn -= 1;
+        // Find a far enough position, or the farthest drawn
         let mut rng = thread_rng();
-        let mut x;
-        let mut y;
-        loop {
-            x = rng.gen_range(-arena.size.x() / 4.0, arena.size.x() / 4.0);
-            y = rng.gen_range(-arena.size.y() / 4.0, arena.size.y() / 4.0);
-            if ship_transforms.iter().all(|transform| {
-                ((transform.translation.x() - x).powi(2) + (transform.translation.y() - y).powi(2))
-                    > 300.0f32.powi(2)
-            }) {
+        let mut farthest = (Vec2::zero(), -1.0);
+        for _ in 0..ASTEROID_SPAWN_ATTEMPTS {
+            let position = Vec2::new(
+                rng.gen_range(-arena.size.x() / 4.0, arena.size.x() / 4.0),
+                rng.gen_range(-arena.size.y() / 4.0, arena.size.y() / 4.0),
+            );
+            let distance = ship_transforms
+                .iter()
+                .map(|transform| (transform.translation.truncate() - position).length())
+                .fold(f32::INFINITY, f32::min);
+            if distance > farthest.1 {
+                farthest = (position, distance);
+            }
+            if distance > ASTEROID_SPAWN_CLEARANCE {
                 break;
             }
         }
-        commands
-            .spawn_with_ghosts(SpriteComponents {
-                material: materials.add(asset_server.load("spaceMeteors_001.png").into()),
-                transform: Transform {
-                    translation: Vec3::new(x, y, -8.0),
-                    scale: Vec3::splat(0.5),
-                    ..Default::default()
-                },
-                ..Default::default()
-            })
-            .with(Armor::new(3))
-            .with(Enemy { xp: 2 })
-            .with(ColliderType::Enemy)
-            .with(outline_materials.add(OutlineMaterial {
-                configuration: OutlineConfiguration {
-                    color: Color::rgb(0.7, 0.7, 1.0),
-                    width: 5,
-                    ..Default::default()
-                },
-                with_outline: false,
-            }));
-        let entity = commands.current_entity().unwrap();
-        let shape = ShapeHandle::new(Ball::new(215.0 * 0.5 * 0.5));
-        let (collision_object_handle, _) = collide_world.add(
-            Isometry2::new(Vector2::new(x, y), na::zero()),
-            shape,
-            collide_groups.enemies,
-            GeometricQueryType::Contacts(0.0, 0.0),
-            entity,
+        let (x, y) = (farthest.0.x(), farthest.0.y());
+        let angle = rng.gen_range(0.0, 2.0 * PI);
+        let entity = spawn_asteroid_entity(
+            &mut commands,
+            &asset_server,
+            &mut materials,
+            &mut outline_materials,
+            &mut collide_world,
+            &collide_groups,
+            AsteroidSpec {
+                position: Vec2::new(x, y),
+                speed: Vec2::new(angle.cos(), angle.sin()) * rng.gen_range(20.0, 80.0),
+                angvel: rng.gen_range(-0.5, 0.5),
+                scale: 0.5,
+            },
         );
-        commands.insert(entity, (collision_object_handle,));
+        commands.insert_one(entity, WaveMember);
     }
 }
-fn camera_follow_system(
-    mut arena: ResMut<Arena>,
-    query_transform: Query<(&FollowedCamera, Changed<Transform>)>,
-    mut query_camera: Query<With<Camera, Mut<Transform>>>,
-) {
-    for (followed_camera, transform) in &mut query_transform.iter() {
-        if let Ok(mut camera_transform) =
-            query_camera.get_component_mut::<Transform>(followed_camera.0)
-        {
-            camera_transform.translation = transform.translation;
-            let shown = match (transform.translation.x(), transform.translation.y()) {
-                (x, y) if x <= 0.0 && y <= 0.0 => ArenaQuadrant::SW,
-                (x, y) if x <= 0.0 && y > 0.0 => ArenaQuadrant::NW,
-                (x, y) if x > 0.0 && y <= 0.0 => ArenaQuadrant::SE,
-                (x, y) if x > 0.0 && y > 0.0 => ArenaQuadrant::NE,
-                _ => panic!(
-                    "Conditions should have catch everything {} {}",
-                    transform.translation.x(),
-                    transform.translation.y()
-                ),
-            };
-            if arena.shown != shown {
-                arena.shown = shown;
-            }
-        }
-    }
+pub struct AsteroidSpec {
+    pub position: Vec2,
+    pub speed: Vec2,
+    pub angvel: f32,
+    /// 0.5 for a regular asteroid, armor, xp and mass follow the scale.
+    pub scale: f32,
+}
+pub fn spawn_asteroid_entity(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    materials: &mut Assets<ColorMaterial>,
+    outline_materials: &mut Assets<OutlineMaterial>,
+    collide_world: &mut CollisionWorld<f32, Entity>,
+    collide_groups: &CollideGroups,
+    spec: AsteroidSpec,
+) -> Entity {
+    let (x, y) = (spec.position.x(), spec.position.y());
+    commands
+        .spawn_with_ghosts(SpriteComponents {
+            material: materials.add(asset_server.load("spaceMeteors_001.png").into()),
+            transform: Transform {
+                translation: Vec3::new(x, y, -8.0),
+                scale: Vec3::splat(spec.scale),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+        .with(Armor::new(((6.0 * spec.scale).round() as u32).max(1)))
+        .with(Movement {
+            speed: spec.speed,
+            dampening: 1.0,
+            angvel: spec.angvel,
+            mass: 32.0 * spec.scale * spec.scale,
+        })
+        .with(Enemy {
+            xp: ((4.0 * spec.scale).round() as u32).max(1),
+        })
+        .with(ColliderType::Enemy)
+        .with(outline_materials.add(OutlineMaterial {
+            configuration: OutlineConfiguration {
+                color: Color::rgb(0.7, 0.7, 1.0),
+                width: 5,
+                ..Default::default()
+            },
+            with_outline: false,
+        }));
+    let entity = commands.current_entity().unwrap();
+    let shape = ShapeHandle::new(Ball::new(215.0 * spec.scale * 0.5));
+    let (collision_object_handle, _) = collide_world.add(
+        Isometry2::new(Vector2::new(x, y), na::zero()),
+        shape,
+        collide_groups.enemies,
+        GeometricQueryType::Contacts(0.0, 0.0),
+        entity,
+    );
+    commands.insert(entity, (collision_object_handle,));
+    entity
 }
@@ -0,0 +1,335 @@
+use std::time::Duration;
+
+use super::*;
+use rodio::{OutputStreamHandle, Sink, Source};
+
+const MUSIC_SAMPLE_RATE: u32 = 22050;
+/// Beats per minute of the gameplay layers, shared so they stay in time when crossfading.
+const GAMEPLAY_BPM: f32 = 120.0;
+/// Seconds for a layer to fade fully in or out.
+const CROSSFADE_DURATION: f32 = 2.0;
+/// Enemies closer than this many views from the camera count toward the intensity.
+const NEARBY_VIEWS: f32 = 1.0;
+/// Nearby enemies for the combat layer to be fully in.
+const COMBAT_ENEMIES: f32 = 4.0;
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum MusicLayer {
+    Calm,
+    Combat,
+    Menu,
+    GameOver,
+}
+
+#[derive(Copy, Clone)]
+enum Waveform {
+    Sine,
+    Triangle,
+    Square,
+    Noise,
+}
+
+/// One instrument of a layer, notes are (midi note or rest, beats).
+struct Voice {
+    waveform: Waveform,
+    amplitude: f32,
+    /// Notes fade out over their length, rather than sustain.
+    plucked: bool,
+    bpm: f32,
+    notes: &'static [(Option<u8>, f32)],
+}
+
+// A minor, F, C, G progression over 16 beats, shared by the gameplay layers.
+static CALM_PAD: [(Option<u8>, f32); 4] = [
+    (Some(45), 4.0),
+    (Some(41), 4.0),
+    (Some(48), 4.0),
+    (Some(43), 4.0),
+];
+#[rustfmt::skip]
+static CALM_ARPEGGIO: [(Option<u8>, f32); 16] = [
+    (Some(69), 1.0), (Some(72), 1.0), (Some(76), 1.0), (Some(72), 1.0),
+    (Some(65), 1.0), (Some(69), 1.0), (Some(72), 1.0), (Some(69), 1.0),
+    (Some(67), 1.0), (Some(72), 1.0), (Some(76), 1.0), (Some(72), 1.0),
+    (Some(67), 1.0), (Some(71), 1.0), (Some(74), 1.0), (Some(71), 1.0),
+];
+#[rustfmt::skip]
+static COMBAT_BASS: [(Option<u8>, f32); 16] = [
+    (Some(33), 0.5), (Some(33), 0.5), (Some(45), 0.5), (Some(33), 0.5),
+    (Some(33), 0.5), (Some(45), 0.5), (Some(33), 0.5), (Some(45), 0.5),
+    (Some(29), 0.5), (Some(29), 0.5), (Some(41), 0.5), (Some(29), 0.5),
+    (Some(36), 0.5), (Some(36), 0.5), (Some(31), 0.5), (Some(43), 0.5),
+];
+static COMBAT_HATS: [(Option<u8>, f32); 4] =
+    [(None, 0.5), (Some(100), 0.5), (None, 0.5), (Some(100), 0.5)];
+#[rustfmt::skip]
+static MENU_MELODY: [(Option<u8>, f32); 8] = [
+    (Some(64), 2.0), (Some(67), 2.0), (Some(69), 3.0), (None, 1.0),
+    (Some(67), 2.0), (Some(64), 2.0), (Some(62), 3.0), (None, 1.0),
+];
+static MENU_PAD: [(Option<u8>, f32); 2] = [(Some(48), 8.0), (Some(43), 8.0)];
+static GAME_OVER_MELODY: [(Option<u8>, f32); 5] = [
+    (Some(57), 2.0),
+    (Some(55), 2.0),
+    (Some(53), 2.0),
+    (Some(52), 4.0),
+    (None, 2.0),
+];
+
+impl MusicLayer {
+    pub const ALL: [MusicLayer; 4] = [
+        MusicLayer::Calm,
+        MusicLayer::Combat,
+        MusicLayer::Menu,
+        MusicLayer::GameOver,
+    ];
+    /// The gameplay layers share their tempo and are paused together, to stay in time.
+    fn is_gameplay(&self) -> bool {
+        *self == MusicLayer::Calm || *self == MusicLayer::Combat
+    }
+    fn voices(&self) -> Vec<Voice> {
+        let voice = |waveform, amplitude, plucked, bpm, notes| Voice {
+            waveform,
+            amplitude,
+            plucked,
+            bpm,
+            notes,
+        };
+        match self {
+            MusicLayer::Calm => vec![
+                voice(Waveform::Sine, 0.25, false, GAMEPLAY_BPM, &CALM_PAD[..]),
+                voice(
+                    Waveform::Triangle,
+                    0.08,
+                    true,
+                    GAMEPLAY_BPM,
+                    &CALM_ARPEGGIO[..],
+                ),
+            ],
+            MusicLayer::Combat => vec![
+                voice(Waveform::Square, 0.08, true, GAMEPLAY_BPM, &COMBAT_BASS[..]),
+                voice(Waveform::Noise, 0.05, true, GAMEPLAY_BPM, &COMBAT_HATS[..]),
+            ],
+            MusicLayer::Menu => vec![
+                voice(Waveform::Triangle, 0.12, true, 90.0, &MENU_MELODY[..]),
+                voice(Waveform::Sine, 0.15, false, 90.0, &MENU_PAD[..]),
+            ],
+            MusicLayer::GameOver => vec![voice(
+                Waveform::Sine,
+                0.2,
+                false,
+                60.0,
+                &GAME_OVER_MELODY[..],
+            )],
+        }
+    }
+}
+
+fn midi_frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+struct VoicePlayer {
+    voice: Voice,
+    note: usize,
+    sample_in_note: usize,
+    phase: f32,
+    noise: u32,
+}
+
+impl VoicePlayer {
+    fn note_samples(&self) -> usize {
+        let beats = self.voice.notes[self.note].1;
+        (beats * 60.0 / self.voice.bpm * MUSIC_SAMPLE_RATE as f32) as usize
+    }
+    fn next_sample(&mut self) -> f32 {
+        let length = self.note_samples();
+        if self.sample_in_note >= length {
+            self.sample_in_note = 0;
+            self.note = (self.note + 1) % self.voice.notes.len();
+        }
+        let (note, _) = self.voice.notes[self.note];
+        let t = self.sample_in_note as f32 / length as f32;
+        self.sample_in_note += 1;
+        let note = match note {
+            Some(note) => note,
+            None => return 0.0,
+        };
+        self.phase = (self.phase + midi_frequency(note) / MUSIC_SAMPLE_RATE as f32).fract();
+        let value = match self.voice.waveform {
+            Waveform::Sine => (self.phase * 2.0 * PI).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Noise => {
+                // xorshift, enough for hi-hats
+                self.noise ^= self.noise << 13;
+                self.noise ^= self.noise >> 17;
+                self.noise ^= self.noise << 5;
+                self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+            }
+        };
+        // Short attack and release avoid clicks between notes.
+        let attack = (self.sample_in_note as f32 / (0.005 * MUSIC_SAMPLE_RATE as f32)).min(1.0);
+        let envelope = if self.voice.plucked {
+            (1.0 - t) * (1.0 - t)
+        } else {
+            ((1.0 - t) * 10.0).min(1.0)
+        };
+        value * attack * envelope * self.voice.amplitude
+    }
+}
+
+/// Endless synthesized music of one layer.
+/// There are no music assets, the tracks are simple enough to be generated.
+pub struct MusicSource {
+    voices: Vec<VoicePlayer>,
+}
+
+impl MusicSource {
+    fn new(layer: MusicLayer) -> Self {
+        MusicSource {
+            voices: layer
+                .voices()
+                .into_iter()
+                .map(|voice| VoicePlayer {
+                    voice,
+                    note: 0,
+                    sample_in_note: 0,
+                    phase: 0.0,
+                    noise: 0x1234_5678,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl Iterator for MusicSource {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        Some(self.voices.iter_mut().map(VoicePlayer::next_sample).sum())
+    }
+}
+
+impl Source for MusicSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        1
+    }
+    fn sample_rate(&self) -> u32 {
+        MUSIC_SAMPLE_RATE
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A layer always playing, its volume follows the game state.
+pub struct MusicPlayer {
+    layer: MusicLayer,
+    sink: Sink,
+    gain: f32,
+}
+
+impl MusicPlayer {
+    pub fn new(layer: MusicLayer, handle: &OutputStreamHandle) -> Option<Self> {
+        let sink = Sink::try_new(handle).ok()?;
+        sink.set_volume(0.0);
+        sink.append(MusicSource::new(layer));
+        Some(MusicPlayer {
+            layer,
+            sink,
+            gain: 0.0,
+        })
+    }
+}
+
+/// How intense the fight is, between 0 and 1.
+#[derive(Default)]
+pub struct MusicState {
+    pub intensity: f32,
+}
+
+/// Count the enemies around the camera, a cleared wave is always calm.
+pub fn music_intensity_system(
+    arena: Res<Arena>,
+    wave: Res<Wave>,
+    mut music_state: ResMut<MusicState>,
+    enemies: Query<With<WaveMember, &Transform>>,
+) {
+    let nearby_distance = arena.view_size.length() * NEARBY_VIEWS;
+    let nearby = enemies
+        .iter()
+        .filter(|transform| {
+            arena
+                .wrapped_delta(arena.view_center, transform.translation.truncate())
+                .length()
+                < nearby_distance
+        })
+        .count();
+    music_state.intensity = if wave.is_cleared() {
+        0.0
+    } else {
+        (nearby as f32 / COMBAT_ENEMIES).min(1.0)
+    };
+}
+
+pub fn setup_music(_world: &mut World, resources: &mut Resources) {
+    let mut mixer = resources.get_thread_local_mut::<AudioMixer>().unwrap();
+    if let Some(handle) = mixer.output_handle() {
+        let players = MusicLayer::ALL
+            .iter()
+            .filter_map(|&layer| MusicPlayer::new(layer, &handle))
+            .collect();
+        mixer.music = players;
+    }
+}
+
+/// Crossfade the layers toward the ones matching the game state.
+/// Silent layers are paused, so they aren't synthesized for nothing.
+pub fn music_system(_world: &mut World, resources: &mut Resources) {
+    let mut mixer = resources.get_thread_local_mut::<AudioMixer>().unwrap();
+    let time = resources.get::<Time>().unwrap();
+    let state = resources.get::<GameState>().unwrap();
+    let music_state = resources.get::<MusicState>().unwrap();
+    let settings = resources.get::<Settings>().unwrap();
+    let volume = settings.audio.output_volume(VolumeBus::Music);
+    let max_change = time.delta_seconds / CROSSFADE_DURATION;
+    for player in mixer.music.iter_mut() {
+        let target = match (*state, player.layer) {
+            (GameState::Playing, MusicLayer::Calm) => 1.0 - music_state.intensity,
+            (GameState::Playing, MusicLayer::Combat) => music_state.intensity,
+            (GameState::Shop, MusicLayer::Menu)
+            | (GameState::LevelUp, MusicLayer::Menu)
+            | (GameState::Settings, MusicLayer::Menu) => 1.0,
+            (GameState::GameOver, MusicLayer::GameOver) => 1.0,
+            _ => 0.0,
+        };
+        player.gain += (target - player.gain).max(-max_change).min(max_change);
+        player.sink.set_volume(player.gain * volume);
+    }
+    let gameplay_silent = mixer
+        .music
+        .iter()
+        .filter(|player| player.layer.is_gameplay())
+        .all(|player| player.gain == 0.0);
+    for player in mixer.music.iter() {
+        let silent = if player.layer.is_gameplay() {
+            gameplay_silent
+        } else {
+            player.gain == 0.0
+        };
+        if silent && !player.sink.is_paused() {
+            player.sink.pause();
+        } else if !silent && player.sink.is_paused() {
+            player.sink.play();
+        }
+    }
+}
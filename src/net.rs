@@ -0,0 +1,1080 @@
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    io::{self, ErrorKind},
+    net::{SocketAddr, ToSocketAddrs, UdpSocket},
+};
+
+use super::*;
+use bevy_prototype_input_map::OnActionActive;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+pub const NET_PORT: u16 = 7777;
+/// Largest UDP payload, snapshots over it are dropped.
+const MAX_PACKET_SIZE: usize = 65_507;
+/// Players joining a server, on top of the host.
+const MAX_CLIENTS: usize = 3;
+const SNAPSHOT_RATE: f32 = 20.0;
+/// Clients render this far in the past, so there is a snapshot on both sides of the render time.
+const INTERPOLATION_DELAY: f64 = 0.1;
+/// Seconds without a packet before a peer is dropped.
+const PEER_TIMEOUT: f64 = 5.0;
+/// Seconds between two Hello, until the server answers.
+const HELLO_INTERVAL: f32 = 1.0;
+const LOOPBACK_REPORT_INTERVAL: f32 = 5.0;
+
+/// How this instance takes part in an online game.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NetRole {
+    Offline,
+    /// Runs the simulation for the joined clients, --host PORT.
+    Server(u16),
+    /// Sends inputs and shows the server snapshots, --join HOST:PORT.
+    Client(SocketAddr),
+    /// Server with headless clients in the same process over localhost, --net-loopback CLIENTS.
+    Loopback(usize),
+    /// 1v1 rollback match against the peer at --versus HOST:PORT, from the local --versus-port.
+    Versus(SocketAddr, u16),
+    /// 1v1 rollback match against a bot in the same process, its packets delayed by
+    /// --versus-loopback FRAMES.
+    VersusLoopback(u32),
+}
+
+impl NetRole {
+    pub fn from_args() -> NetRole {
+        if let Some(port) = arg_value("--host") {
+            return NetRole::Server(port.parse().unwrap_or_else(|_| {
+                println!("Invalid port {}, using {}", port, NET_PORT);
+                NET_PORT
+            }));
+        }
+        if let Some(address) = arg_value("--join") {
+            match address
+                .to_socket_addrs()
+                .map(|mut addresses| addresses.next())
+            {
+                Ok(Some(address)) => return NetRole::Client(address),
+                _ => println!("Invalid server {}, expected HOST:PORT", address),
+            }
+        }
+        if let Some(clients) = arg_value("--net-loopback") {
+            match clients.parse() {
+                Ok(clients @ 1..=MAX_CLIENTS) => return NetRole::Loopback(clients),
+                _ => println!(
+                    "Invalid loopback clients {}, expected 1 to {}",
+                    clients, MAX_CLIENTS
+                ),
+            }
+        }
+        if let Some(address) = arg_value("--versus") {
+            let port = arg_value("--versus-port")
+                .and_then(|port| port.parse().ok())
+                .unwrap_or(NET_PORT);
+            match address
+                .to_socket_addrs()
+                .map(|mut addresses| addresses.next())
+            {
+                Ok(Some(address)) => return NetRole::Versus(address, port),
+                _ => println!("Invalid peer {}, expected HOST:PORT", address),
+            }
+        }
+        if let Some(delay) = arg_value("--versus-loopback") {
+            match delay.parse() {
+                Ok(delay) => return NetRole::VersusLoopback(delay),
+                Err(_) => println!("Invalid loopback delay {}, expected frames", delay),
+            }
+        }
+        NetRole::Offline
+    }
+}
+
+/// Controls sent by a client each frame, applied by the server to its ship.
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Debug, PartialEq)]
+pub struct InputFrame {
+    /// Forward thrust, in [-1, 1].
+    pub thrust: f32,
+    /// Lateral thrust to the left, in [-1, 1].
+    pub strafe: f32,
+    /// Heading to steer to, None to keep the current one.
+    pub aim: Option<f32>,
+    pub fire: bool,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum NetKind {
+    Ship,
+    Enemy,
+    Missile,
+    Loot,
+}
+
+impl NetKind {
+    fn from_collider(collider_type: ColliderType) -> Option<NetKind> {
+        match collider_type {
+            ColliderType::Ship => Some(NetKind::Ship),
+            ColliderType::Enemy => Some(NetKind::Enemy),
+            ColliderType::Missile => Some(NetKind::Missile),
+            ColliderType::Loot => Some(NetKind::Loot),
+            ColliderType::Cursor => None,
+        }
+    }
+    fn asset(&self) -> &'static str {
+        match self {
+            NetKind::Ship => "playerShip1_red.png",
+            NetKind::Enemy => "spaceMeteors_001.png",
+            NetKind::Missile => "laserRed07.png",
+            NetKind::Loot => "sprite_sphere_256x256.png",
+        }
+    }
+    /// Same depth as the entities spawned by the server.
+    fn z(&self) -> f32 {
+        match self {
+            NetKind::Ship => 0.0,
+            NetKind::Enemy => -8.0,
+            NetKind::Missile => -0.1,
+            NetKind::Loot => -0.2,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq)]
+pub struct EntityState {
+    pub id: u32,
+    pub kind: NetKind,
+    pub position: (f32, f32),
+    pub rotation: f32,
+    pub scale: f32,
+    /// Tint of the loot, as there is a single loot sprite on clients.
+    pub color: Option<(f32, f32, f32)>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub tick: u32,
+    /// Server time, in seconds since its startup.
+    pub time: f64,
+    pub entities: Vec<EntityState>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ClientMessage {
+    Hello { ship_class: ShipClass },
+    Input { tick: u32, input: InputFrame },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ServerMessage {
+    Welcome {
+        ship: u32,
+        arena_size: (f32, f32),
+        arena_edge: ArenaEdge,
+    },
+    Full,
+    Snapshot(Snapshot),
+}
+
+/// Identifier of a replicated entity, shared by the server and its clients.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub struct NetId(pub u32);
+
+/// Last input received for the ship of a client.
+pub struct RemoteInput(pub InputFrame);
+
+pub fn send_packet<T: Serialize>(socket: &UdpSocket, address: SocketAddr, message: &T) {
+    match ron::ser::to_string(message) {
+        Ok(text) if text.len() <= MAX_PACKET_SIZE => {
+            if let Err(e) = socket.send_to(text.as_bytes(), address) {
+                println!("Failed to send to {}: {}", address, e);
+            }
+        }
+        Ok(text) => println!("Dropped a {} bytes packet to {}", text.len(), address),
+        Err(e) => println!("Failed to serialize a packet: {}", e),
+    }
+}
+
+/// All the packets waiting on the non blocking socket.
+pub fn receive_packets<T: DeserializeOwned>(socket: &UdpSocket) -> Vec<(SocketAddr, T)> {
+    let mut buffer = vec![0; MAX_PACKET_SIZE];
+    let mut messages = Vec::new();
+    loop {
+        match socket.recv_from(&mut buffer) {
+            Ok((len, address)) => match ron::de::from_bytes(&buffer[..len]) {
+                Ok(message) => messages.push((address, message)),
+                Err(e) => println!("Ignored a malformed packet from {}: {}", address, e),
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => {
+                println!("Failed to receive: {}", e);
+                break;
+            }
+        }
+    }
+    messages
+}
+
+pub fn bind_socket(address: SocketAddr) -> io::Result<UdpSocket> {
+    let socket = UdpSocket::bind(address)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket)
+}
+
+struct RemoteClient {
+    /// Spawned by net_server_system after the Hello.
+    ship: Option<Entity>,
+    ship_id: u32,
+    ship_class: ShipClass,
+    position: Vec2,
+    last_seen: f64,
+    last_input_tick: u32,
+    /// Last input received, copied to the RemoteInput of the ship.
+    input: InputFrame,
+}
+
+pub struct NetServer {
+    socket: UdpSocket,
+    clients: HashMap<SocketAddr, RemoteClient>,
+    next_id: u32,
+    tick: u32,
+    snapshot_timer: Timer,
+}
+
+impl NetServer {
+    fn bind(address: SocketAddr) -> io::Result<NetServer> {
+        let socket = bind_socket(address)?;
+        println!("Hosting on {}", socket.local_addr()?);
+        Ok(NetServer {
+            socket,
+            clients: HashMap::new(),
+            next_id: 0,
+            tick: 0,
+            snapshot_timer: Timer::from_seconds(1.0 / SNAPSHOT_RATE, true),
+        })
+    }
+    fn next_id(&mut self) -> NetId {
+        self.next_id += 1;
+        NetId(self.next_id)
+    }
+    /// Handle the client packets: welcome the new clients and keep their last input.
+    fn receive(&mut self, now: f64, arena: &Arena) {
+        for (address, message) in receive_packets::<ClientMessage>(&self.socket) {
+            match message {
+                ClientMessage::Hello { ship_class } => {
+                    if !self.clients.contains_key(&address) {
+                        if self.clients.len() >= MAX_CLIENTS {
+                            send_packet(&self.socket, address, &ServerMessage::Full);
+                            continue;
+                        }
+                        let position = Vec2::new(0.0, -100.0 * (self.clients.len() + 1) as f32);
+                        let ship_id = self.next_id().0;
+                        println!("Client {} joined with ship {}", address, ship_id);
+                        self.clients.insert(
+                            address,
+                            RemoteClient {
+                                ship: None,
+                                ship_id,
+                                ship_class,
+                                position,
+                                last_seen: now,
+                                last_input_tick: 0,
+                                input: InputFrame::default(),
+                            },
+                        );
+                    }
+                    // Hello is repeated until the Welcome gets through.
+                    let welcome = ServerMessage::Welcome {
+                        ship: self.clients[&address].ship_id,
+                        arena_size: (arena.size.x(), arena.size.y()),
+                        arena_edge: arena.edge,
+                    };
+                    send_packet(&self.socket, address, &welcome);
+                }
+                ClientMessage::Input { tick, input } => {
+                    if let Some(client) = self.clients.get_mut(&address) {
+                        client.last_seen = now;
+                        if tick > client.last_input_tick {
+                            client.last_input_tick = tick;
+                            client.input = input;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    /// Drop the clients silent for too long, returning their ships.
+    fn remove_timed_out(&mut self, now: f64) -> Vec<Entity> {
+        let timed_out: Vec<SocketAddr> = self
+            .clients
+            .iter()
+            .filter(|(_, client)| now - client.last_seen > PEER_TIMEOUT)
+            .map(|(&address, _)| address)
+            .collect();
+        timed_out
+            .into_iter()
+            .filter_map(|address| {
+                println!("Client {} timed out", address);
+                self.clients.remove(&address).unwrap().ship
+            })
+            .collect()
+    }
+    /// Send the replicated entities to every client.
+    fn send_snapshot(&mut self, time: f64, entities: Vec<EntityState>) {
+        self.tick += 1;
+        let message = ServerMessage::Snapshot(Snapshot {
+            tick: self.tick,
+            time,
+            entities,
+        });
+        for &address in self.clients.keys() {
+            send_packet(&self.socket, address, &message);
+        }
+    }
+}
+
+pub struct NetClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    ship_class: ShipClass,
+    /// NetId of the own ship, once welcomed by the server.
+    pub ship: Option<u32>,
+    /// Replicated entities, from the server NetId to the local entity.
+    pub entities: HashMap<u32, Entity>,
+    snapshots: VecDeque<Snapshot>,
+    /// Smallest arrival time minus server time seen, the server clock is the local one minus it.
+    clock_offset: Option<f64>,
+    hello_timer: Timer,
+    input_tick: u32,
+    last_seen: f64,
+    pub snapshots_received: u32,
+}
+
+impl NetClient {
+    fn connect(server: SocketAddr, ship_class: ShipClass) -> io::Result<NetClient> {
+        let local: SocketAddr = if server.is_ipv4() {
+            "0.0.0.0:0".parse().unwrap()
+        } else {
+            "[::]:0".parse().unwrap()
+        };
+        let socket = bind_socket(local)?;
+        println!("Joining {}", server);
+        send_packet(&socket, server, &ClientMessage::Hello { ship_class });
+        Ok(NetClient {
+            socket,
+            server,
+            ship_class,
+            ship: None,
+            entities: HashMap::new(),
+            snapshots: VecDeque::new(),
+            clock_offset: None,
+            hello_timer: Timer::from_seconds(HELLO_INTERVAL, true),
+            input_tick: 0,
+            last_seen: 0.0,
+            snapshots_received: 0,
+        })
+    }
+    /// Handle the server packets, return the arena of the server once welcomed.
+    fn receive(&mut self, now: f64, delta_seconds: f32) -> Option<(Vec2, ArenaEdge)> {
+        let mut arena = None;
+        if self.ship.is_none() {
+            self.hello_timer.tick(delta_seconds);
+            if self.hello_timer.just_finished {
+                let hello = ClientMessage::Hello {
+                    ship_class: self.ship_class,
+                };
+                send_packet(&self.socket, self.server, &hello);
+            }
+        }
+        for (address, message) in receive_packets::<ServerMessage>(&self.socket) {
+            if address != self.server {
+                continue;
+            }
+            self.last_seen = now;
+            match message {
+                ServerMessage::Welcome {
+                    ship,
+                    arena_size,
+                    arena_edge,
+                } => {
+                    if self.ship.is_none() {
+                        println!("Joined {} with ship {}", self.server, ship);
+                    }
+                    self.ship = Some(ship);
+                    arena = Some((Vec2::new(arena_size.0, arena_size.1), arena_edge));
+                }
+                ServerMessage::Full => println!("Server {} is full", self.server),
+                ServerMessage::Snapshot(snapshot) => {
+                    // Packets can be reordered, older snapshots are useless.
+                    if self
+                        .snapshots
+                        .back()
+                        .map_or(false, |last| last.tick >= snapshot.tick)
+                    {
+                        continue;
+                    }
+                    let offset = now - snapshot.time;
+                    self.clock_offset = Some(self.clock_offset.map_or(offset, |o| o.min(offset)));
+                    self.snapshots_received += 1;
+                    self.snapshots.push_back(snapshot);
+                }
+            }
+        }
+        if self.ship.is_some() && now - self.last_seen > PEER_TIMEOUT {
+            println!("Lost connection to {}", self.server);
+            self.ship = None;
+            self.last_seen = now;
+        }
+        arena
+    }
+    fn send_input(&mut self, input: InputFrame) {
+        if self.ship.is_none() {
+            return;
+        }
+        self.input_tick += 1;
+        let message = ClientMessage::Input {
+            tick: self.input_tick,
+            input,
+        };
+        send_packet(&self.socket, self.server, &message);
+    }
+    /// Snapshots around the render time, and how far the render time is between them.
+    fn interpolation_pair(&mut self, now: f64) -> Option<(&Snapshot, &Snapshot, f32)> {
+        let render_time = now - self.clock_offset? - INTERPOLATION_DELAY;
+        // Keep a single snapshot before the render time.
+        while self.snapshots.len() > 2 && self.snapshots[1].time <= render_time {
+            self.snapshots.pop_front();
+        }
+        let from = self.snapshots.front()?;
+        let to = self.snapshots.get(1).unwrap_or(from);
+        let alpha = if to.time > from.time {
+            ((render_time - from.time) / (to.time - from.time))
+                .max(0.0)
+                .min(1.0) as f32
+        } else {
+            1.0
+        };
+        Some((from, to, alpha))
+    }
+}
+
+/// Headless client of the loopback harness, flying in circles and shooting.
+pub struct LoopbackBot {
+    client: NetClient,
+    heading: f32,
+}
+
+pub struct Network {
+    pub server: Option<NetServer>,
+    pub client: Option<NetClient>,
+    pub bots: Vec<LoopbackBot>,
+    pub versus: Option<RollbackSession>,
+    /// Opponent of the versus loopback harness.
+    pub versus_bot: Option<RollbackSession>,
+    pub report_timer: Timer,
+}
+
+impl Network {
+    pub fn from_args(profile: &Profile) -> Network {
+        let mut network = Network {
+            server: None,
+            client: None,
+            bots: Vec::new(),
+            versus: None,
+            versus_bot: None,
+            report_timer: Timer::from_seconds(LOOPBACK_REPORT_INTERVAL, true),
+        };
+        let result = match NetRole::from_args() {
+            NetRole::Offline => Ok(()),
+            NetRole::Server(port) => NetServer::bind(SocketAddr::from(([0, 0, 0, 0], port)))
+                .map(|server| network.server = Some(server)),
+            NetRole::Client(address) => NetClient::connect(address, profile.selected_ship)
+                .map(|client| network.client = Some(client)),
+            NetRole::Loopback(clients) => NetServer::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+                .and_then(|server| {
+                    let address = server.socket.local_addr()?;
+                    network.server = Some(server);
+                    for bot in 0..clients {
+                        network.bots.push(LoopbackBot {
+                            client: NetClient::connect(address, profile.selected_ship)?,
+                            heading: bot as f32 * 2.0 * PI / clients as f32,
+                        });
+                    }
+                    Ok(())
+                }),
+            NetRole::Versus(peer, port) => {
+                let local = if peer.is_ipv4() {
+                    SocketAddr::from(([0, 0, 0, 0], port))
+                } else {
+                    SocketAddr::from(([0u16; 8], port))
+                };
+                bind_socket(local).map(|socket| {
+                    println!("Versus match from port {} against {}", port, peer);
+                    network.versus = Some(RollbackSession::new(
+                        socket,
+                        peer,
+                        Arena::size_from_args(),
+                        0,
+                    ));
+                })
+            }
+            NetRole::VersusLoopback(delay) => {
+                let localhost = SocketAddr::from(([127, 0, 0, 1], 0));
+                bind_socket(localhost).and_then(|socket| {
+                    let bot_socket = bind_socket(localhost)?;
+                    let address = socket.local_addr()?;
+                    let bot_address = bot_socket.local_addr()?;
+                    let arena_size = Arena::size_from_args();
+                    network.versus = Some(RollbackSession::new(socket, bot_address, arena_size, 0));
+                    network.versus_bot =
+                        Some(RollbackSession::new(bot_socket, address, arena_size, delay));
+                    Ok(())
+                })
+            }
+        };
+        if let Err(e) = result {
+            println!("Failed to start the network, playing offline: {}", e);
+            network.server = None;
+            network.client = None;
+            network.bots.clear();
+            network.versus = None;
+            network.versus_bot = None;
+        }
+        network
+    }
+    /// Whether the waves and player ships are simulated here, not by a server or a versus match.
+    pub fn simulates_waves(&self) -> bool {
+        self.client.is_none() && self.versus.is_none()
+    }
+}
+
+/// Accept clients, spawning their ship, and store their inputs.
+pub fn net_server_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    (mut network, arena): (ResMut<Network>, Res<Arena>),
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    (mut collide_world, collide_groups): (ResMut<CollisionWorld<f32, Entity>>, Res<CollideGroups>),
+    mut remote_inputs: Query<Mut<RemoteInput>>,
+) {
+    let server = match network.server.as_mut() {
+        Some(server) => server,
+        None => return,
+    };
+    let now = time.seconds_since_startup;
+    server.receive(now, &arena);
+    for client in server.clients.values_mut() {
+        let ship = match client.ship {
+            Some(ship) => ship,
+            None => {
+                let ship = spawn_ship(
+                    &mut commands,
+                    &asset_server,
+                    &mut materials,
+                    &mut collide_world,
+                    &collide_groups,
+                    client.ship_class,
+                    client.position,
+                );
+                commands.insert(
+                    ship,
+                    (
+                        NetId(client.ship_id),
+                        InputDevice::Remote,
+                        RemoteInput(client.input),
+                    ),
+                );
+                client.ship = Some(ship);
+                continue;
+            }
+        };
+        if let Ok(mut remote_input) = remote_inputs.get_component_mut::<RemoteInput>(ship) {
+            remote_input.0 = client.input;
+        }
+    }
+    for ship in server.remove_timed_out(now) {
+        if remote_inputs.get_component::<RemoteInput>(ship).is_ok() {
+            commands.despawn_from_arena(ship);
+        }
+    }
+}
+
+/// Steer the client ships from their last input.
+pub fn remote_input_system(
+    time: Res<Time>,
+    state: Res<GameState>,
+    mut fire_weapon_events: ResMut<Events<FireWeaponEvent>>,
+    mut ships: Query<(
+        Entity,
+        &RemoteInput,
+        &Spaceship,
+        Mut<Movement>,
+        &Transform,
+        Mut<Weapon>,
+    )>,
+    mut emitters: Query<Mut<ParticleEmitter>>,
+) {
+    if *state != GameState::Playing {
+        return;
+    }
+    let dt = time.delta_seconds;
+    for (ship_entity, remote_input, ship, mut movement, ship_transform, mut weapon) in
+        ships.iter_mut()
+    {
+        let input = remote_input.0;
+        if input.fire {
+            fire_weapon(ship_entity, &mut weapon, &mut fire_weapon_events);
+        }
+        let thrust = input.thrust.max(-1.0).min(1.0);
+        let strafe = input.strafe.max(-1.0).min(1.0);
+        let forward = (ship_transform.rotation * Vec3::unit_x()).truncate();
+        let left = Vec2::new(-forward.y(), forward.x());
+        movement.speed +=
+            (forward * thrust * ship.max_linvel + left * strafe * ship.max_latvel) * dt;
+        if let Some(aim) = input.aim {
+            let angle = rotation_angle(&ship_transform.rotation);
+            movement.angvel = ship.steer(movement.angvel, angle, aim, dt);
+        }
+        if thrust > 0.0 {
+            if let Ok(mut emitter) = emitters.get_component_mut::<ParticleEmitter>(ship_entity) {
+                emitter.active = true;
+            }
+        }
+    }
+}
+
+/// Send the replicated entities to the clients, giving a NetId to the new ones.
+pub fn net_snapshot_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut network: ResMut<Network>,
+    replicated: Query<(
+        Entity,
+        &ColliderType,
+        &Transform,
+        Option<&NetId>,
+        Option<&Loot>,
+    )>,
+) {
+    let server = match network.server.as_mut() {
+        Some(server) => server,
+        None => return,
+    };
+    let mut entities = Vec::new();
+    for (entity, &collider_type, transform, net_id, loot) in replicated.iter() {
+        let kind = match NetKind::from_collider(collider_type) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let id = match net_id {
+            Some(net_id) => net_id.0,
+            None => {
+                // Sent from the next snapshot on.
+                let net_id = server.next_id();
+                commands.insert_one(entity, net_id);
+                continue;
+            }
+        };
+        entities.push(EntityState {
+            id,
+            kind,
+            position: (transform.translation.x(), transform.translation.y()),
+            rotation: rotation_angle(&transform.rotation),
+            scale: transform.scale.x(),
+            color: loot.map(|loot| {
+                let color = loot.get_color();
+                (color.r(), color.g(), color.b())
+            }),
+        });
+    }
+    server.snapshot_timer.tick(time.delta_seconds);
+    if !server.snapshot_timer.just_finished || server.clients.is_empty() {
+        return;
+    }
+    server.send_snapshot(time.seconds_since_startup, entities);
+}
+
+/// Send the local controls to the server or the versus peer, always in the cursor aim scheme.
+pub fn net_input_system(
+    mut active_reader: Local<EventReader<OnActionActive>>,
+    action_active_events: Res<Events<OnActionActive>>,
+    (mut network, state): (ResMut<Network>, Res<GameState>),
+    (gamepad_state, gamepad_buttons): (Res<GamepadState>, Res<Input<GamepadButton>>),
+    cursor_world_pos: Res<Cursor2dWorldPos>,
+    transforms: Query<&Transform>,
+) {
+    if network.client.is_none() && network.versus.is_none() {
+        return;
+    }
+    let active: HashSet<String> = active_reader
+        .iter(&action_active_events)
+        .map(|active_event| active_event.action.clone())
+        .collect();
+    let input = if *state == GameState::Playing {
+        let ship_position = match (&network.client, &network.versus) {
+            (Some(client), _) => client
+                .ship
+                .and_then(|ship| client.entities.get(&ship))
+                .and_then(|&entity| transforms.get_component::<Transform>(entity).ok())
+                .map(|transform| transform.translation.truncate()),
+            (_, Some(session)) => session.local_ship().map(|ship| ship.position),
+            _ => None,
+        };
+        local_input_frame(
+            &active,
+            &gamepad_state,
+            &gamepad_buttons,
+            cursor_world_pos.world_pos,
+            ship_position,
+        )
+    } else {
+        InputFrame::default()
+    };
+    if let Some(client) = network.client.as_mut() {
+        client.send_input(input);
+    }
+    if let Some(session) = network.versus.as_mut() {
+        session.pending_input = VersusInput::from_frame(input, session.pending_input);
+    }
+}
+
+/// Controls of the keyboard and mouse, or of the gamepad when it was used last.
+fn local_input_frame(
+    active: &HashSet<String>,
+    gamepad_state: &GamepadState,
+    gamepad_buttons: &Input<GamepadButton>,
+    cursor_position: Vec2,
+    ship_position: Option<Vec2>,
+) -> InputFrame {
+    let is_active = |action: &str| active.contains(action) as i32 as f32;
+    let mut input = InputFrame {
+        thrust: is_active(ACTION_FORWARD) - is_active(ACTION_BACKWARD),
+        strafe: is_active(ACTION_RCS_L) - is_active(ACTION_RCS_R),
+        aim: None,
+        fire: active.contains(ACTION_SHOOT_1),
+    };
+    match (gamepad_state.mode, gamepad_state.gamepad) {
+        (InputMode::Gamepad, Some(gamepad)) => {
+            let stick = gamepad_state.left_stick;
+            input.thrust = stick.y();
+            input.strafe = -stick.x();
+            let aim = gamepad_state.right_stick;
+            if aim != Vec2::zero() {
+                input.aim = Some(aim.y().atan2(aim.x()));
+            }
+            input.fire = [
+                GamepadButtonType::RightTrigger2,
+                GamepadButtonType::LeftTrigger2,
+            ]
+            .iter()
+            .any(|&button_type| gamepad_buttons.pressed(GamepadButton(gamepad, button_type)));
+        }
+        _ => {
+            if let Some(ship_position) = ship_position {
+                let delta = cursor_position - ship_position;
+                input.aim = Some(delta.y().atan2(delta.x()));
+            }
+        }
+    }
+    input
+}
+
+impl EntityState {
+    /// Position, rotation and scale between the previous state and this one,
+    /// the short way across the wrapping edges.
+    fn interpolate(&self, previous: &EntityState, alpha: f32, arena: &Arena) -> (Vec2, f32, f32) {
+        let previous_position = Vec2::new(previous.position.0, previous.position.1);
+        let position = Vec2::new(self.position.0, self.position.1);
+        let delta = arena.wrapped_delta(previous_position, position);
+        let rotation = normalize_angle(self.rotation - previous.rotation);
+        (
+            previous_position + delta * alpha,
+            previous.rotation + rotation * alpha,
+            previous.scale + (self.scale - previous.scale) * alpha,
+        )
+    }
+}
+
+/// Receive the server snapshots and show the entities between the two around the render time.
+pub fn net_client_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    (mut network, mut arena): (ResMut<Network>, ResMut<Arena>),
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    cameras: Query<With<CameraRig, Entity>>,
+    mut transforms: Query<Mut<Transform>>,
+) {
+    let client = match network.client.as_mut() {
+        Some(client) => client,
+        None => return,
+    };
+    let now = time.seconds_since_startup;
+    if let Some((size, edge)) = client.receive(now, time.delta_seconds) {
+        if arena.size != size || arena.edge != edge {
+            *arena = Arena::new(size, edge);
+        }
+    }
+    let (from, to, alpha) = match client.interpolation_pair(now) {
+        Some(pair) => pair,
+        None => return,
+    };
+    let previous: HashMap<u32, &EntityState> = from
+        .entities
+        .iter()
+        .map(|state| (state.id, state))
+        .collect();
+    let states: Vec<(EntityState, Vec2, f32, f32)> = to
+        .entities
+        .iter()
+        .map(|state| {
+            let position = Vec2::new(state.position.0, state.position.1);
+            match previous.get(&state.id) {
+                Some(prev) => {
+                    let (position, rotation, scale) = state.interpolate(prev, alpha, &arena);
+                    (*state, position, rotation, scale)
+                }
+                // Just spawned on the server.
+                None => (*state, position, state.rotation, state.scale),
+            }
+        })
+        .collect();
+    let camera = cameras.iter().next();
+    let mut alive = HashSet::new();
+    for (state, position, rotation, scale) in states {
+        alive.insert(state.id);
+        let translation = Vec3::new(position.x(), position.y(), state.kind.z());
+        if let Some(&entity) = client.entities.get(&state.id) {
+            if let Ok(mut transform) = transforms.get_component_mut::<Transform>(entity) {
+                transform.translation = translation;
+                transform.rotation = Quat::from_rotation_z(rotation);
+                transform.scale = Vec3::splat(scale);
+            }
+            continue;
+        }
+        let texture = asset_server.load(state.kind.asset());
+        let color = state
+            .color
+            .map_or(Color::WHITE, |(r, g, b)| Color::rgb(r, g, b));
+        commands
+            .spawn_with_ghosts(SpriteComponents {
+                material: materials.add(ColorMaterial::modulated_texture(texture, color)),
+                transform: Transform {
+                    translation,
+                    rotation: Quat::from_rotation_z(rotation),
+                    scale: Vec3::splat(scale),
+                },
+                ..Default::default()
+            })
+            .with(NetId(state.id));
+        let entity = commands.current_entity().unwrap();
+        if client.ship == Some(state.id) {
+            if let Some(camera) = camera {
+                commands.insert_one(entity, FollowedCamera(camera));
+            }
+        }
+        client.entities.insert(state.id, entity);
+    }
+    let gone: Vec<u32> = client
+        .entities
+        .keys()
+        .filter(|id| !alive.contains(*id))
+        .copied()
+        .collect();
+    for id in gone {
+        commands.despawn_from_arena(client.entities.remove(&id).unwrap());
+    }
+}
+
+/// Drive the headless clients of the loopback harness, and report what they receive.
+pub fn net_loopback_system(time: Res<Time>, mut network: ResMut<Network>) {
+    if network.bots.is_empty() {
+        return;
+    }
+    let now = time.seconds_since_startup;
+    let dt = time.delta_seconds;
+    network.report_timer.tick(dt);
+    let report = network.report_timer.just_finished;
+    for (index, bot) in network.bots.iter_mut().enumerate() {
+        bot.client.receive(now, dt);
+        bot.heading = normalize_angle(bot.heading + dt);
+        bot.client.send_input(InputFrame {
+            thrust: 0.5,
+            strafe: 0.0,
+            aim: Some(bot.heading),
+            fire: true,
+        });
+        if report {
+            let entities = bot
+                .client
+                .snapshots
+                .back()
+                .map_or(0, |snapshot| snapshot.entities.len());
+            println!(
+                "Loopback client {}: ship {:?}, {} snapshots, {} entities in the last one",
+                index, bot.client.ship, bot.client.snapshots_received, entities
+            );
+        }
+        // Headless, nothing is interpolated.
+        while bot.client.snapshots.len() > 1 {
+            bot.client.snapshots.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    fn localhost() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], 0))
+    }
+
+    fn test_arena() -> Arena {
+        Arena::new(Vec2::new(1000.0, 800.0), ArenaEdge::Wrap)
+    }
+
+    /// Retry until the packets went through the loopback interface.
+    fn wait_for(mut done: impl FnMut() -> bool) {
+        for _ in 0..200 {
+            if done() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(5));
+        }
+        panic!("No packet received");
+    }
+
+    /// Server and welcomed client, with the address of the client as seen by the server.
+    fn connect() -> (NetServer, NetClient, SocketAddr) {
+        let arena = test_arena();
+        let mut server = NetServer::bind(localhost()).unwrap();
+        let server_address = server.socket.local_addr().unwrap();
+        let mut client = NetClient::connect(server_address, ShipClass::Interceptor).unwrap();
+        let client_address =
+            SocketAddr::from(([127, 0, 0, 1], client.socket.local_addr().unwrap().port()));
+        wait_for(|| {
+            server.receive(0.0, &arena);
+            server.clients.contains_key(&client_address)
+        });
+        let mut welcome = None;
+        wait_for(|| {
+            welcome = client.receive(0.0, 0.0);
+            welcome.is_some()
+        });
+        assert_eq!(welcome, Some((arena.size, arena.edge)));
+        (server, client, client_address)
+    }
+
+    fn entity_state(id: u32, position: (f32, f32), rotation: f32) -> EntityState {
+        EntityState {
+            id,
+            kind: NetKind::Ship,
+            position,
+            rotation,
+            scale: 0.3,
+            color: None,
+        }
+    }
+
+    #[test]
+    fn hello_gets_a_welcome() {
+        let (server, client, client_address) = connect();
+        let remote = &server.clients[&client_address];
+        assert_eq!(client.ship, Some(remote.ship_id));
+        assert_eq!(remote.ship_class, ShipClass::Interceptor);
+        assert!(remote.ship.is_none());
+    }
+
+    #[test]
+    fn input_reaches_the_client_ship() {
+        let arena = test_arena();
+        let (mut server, mut client, client_address) = connect();
+        let input = InputFrame {
+            thrust: 1.0,
+            strafe: -0.5,
+            aim: Some(1.5),
+            fire: true,
+        };
+        client.send_input(input);
+        wait_for(|| {
+            server.receive(1.0, &arena);
+            server.clients[&client_address].input == input
+        });
+        // An older input arriving late is ignored.
+        let stale = ClientMessage::Input {
+            tick: 0,
+            input: InputFrame::default(),
+        };
+        send_packet(&client.socket, client.server, &stale);
+        wait_for(|| {
+            server.receive(2.0, &arena);
+            server.clients[&client_address].last_seen == 2.0
+        });
+        assert_eq!(server.clients[&client_address].input, input);
+    }
+
+    #[test]
+    fn snapshot_round_trips() {
+        let (mut server, mut client, _) = connect();
+        let mut loot = entity_state(2, (-12.5, 40.0), 0.0);
+        loot.kind = NetKind::Loot;
+        loot.color = Some((1.0, 0.5, 0.25));
+        let entities = vec![entity_state(1, (100.25, -3.5), 1.25), loot];
+        server.send_snapshot(4.5, entities.clone());
+        wait_for(|| {
+            client.receive(5.0, 0.0);
+            client.snapshots_received == 1
+        });
+        let expected = Snapshot {
+            tick: 1,
+            time: 4.5,
+            entities,
+        };
+        assert_eq!(client.snapshots.back(), Some(&expected));
+    }
+
+    #[test]
+    fn interpolation_pair_skips_reordered_snapshots() {
+        let (server, mut client, client_address) = connect();
+        assert!(client.interpolation_pair(10.0).is_none());
+        for &(tick, time) in [(1, 1.0), (3, 2.0), (2, 1.5), (4, 3.0)].iter() {
+            let snapshot = Snapshot {
+                tick,
+                time,
+                entities: Vec::new(),
+            };
+            send_packet(
+                &server.socket,
+                client_address,
+                &ServerMessage::Snapshot(snapshot),
+            );
+        }
+        wait_for(|| {
+            client.receive(10.0, 0.0);
+            client.snapshots_received == 3
+        });
+        let ticks: Vec<u32> = client.snapshots.iter().map(|s| s.tick).collect();
+        assert_eq!(ticks, vec![1, 3, 4]);
+        // The clock offset is 7s, rendering happens INTERPOLATION_DELAY earlier.
+        let (from, to, alpha) = client.interpolation_pair(8.6).unwrap();
+        assert_eq!((from.tick, to.tick), (1, 3));
+        assert!((alpha - 0.5).abs() < 1e-4);
+        let (from, to, alpha) = client.interpolation_pair(9.6).unwrap();
+        assert_eq!((from.tick, to.tick), (3, 4));
+        assert!((alpha - 0.5).abs() < 1e-4);
+        let (from, to, alpha) = client.interpolation_pair(20.0).unwrap();
+        assert_eq!((from.tick, to.tick), (3, 4));
+        assert_eq!(alpha, 1.0);
+    }
+
+    #[test]
+    fn interpolation_wraps_across_the_edges() {
+        let previous = entity_state(1, (490.0, -395.0), PI - 0.1);
+        let state = entity_state(1, (-490.0, 395.0), -PI + 0.1);
+        let (position, rotation, _) = state.interpolate(&previous, 0.5, &test_arena());
+        assert!((position - Vec2::new(500.0, -400.0)).length() < 1e-3);
+        assert!((rotation - PI).abs() < 1e-4);
+        let walls = Arena::new(Vec2::new(1000.0, 800.0), ArenaEdge::Walls);
+        let (position, _, _) = state.interpolate(&previous, 0.5, &walls);
+        assert!(position.length() < 1e-3);
+    }
+}
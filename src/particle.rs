@@ -0,0 +1,290 @@
+use std::{collections::HashMap, f32::consts::PI};
+
+use super::*;
+
+/// Cap on the particles alive at once, all effects together.
+const MAX_PARTICLES: usize = 600;
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum ParticleEffect {
+    Explosion,
+    Impact,
+    Exhaust,
+    Sparkle,
+}
+
+/// How the particles of an effect are emitted and evolve.
+struct EffectSpec {
+    /// Particles per burst, for a size of 1.
+    count: usize,
+    /// Cap on the particles of this effect alive at once.
+    budget: usize,
+    /// Half angle around the direction, PI for all around.
+    spread: f32,
+    speed: (f32, f32),
+    lifetime: (f32, f32),
+    start_color: Color,
+    end_color: Color,
+    start_scale: f32,
+    end_scale: f32,
+    dampening: f32,
+}
+
+impl ParticleEffect {
+    fn spec(&self) -> EffectSpec {
+        match self {
+            ParticleEffect::Explosion => EffectSpec {
+                count: 40,
+                budget: 300,
+                spread: PI,
+                speed: (50.0, 300.0),
+                lifetime: (0.4, 1.0),
+                start_color: Color::rgb(1.0, 0.9, 0.5),
+                end_color: Color::rgba(0.6, 0.1, 0.0, 0.0),
+                start_scale: 0.08,
+                end_scale: 0.02,
+                dampening: 1.5,
+            },
+            ParticleEffect::Impact => EffectSpec {
+                count: 8,
+                budget: 100,
+                spread: PI / 3.0,
+                speed: (100.0, 250.0),
+                lifetime: (0.1, 0.3),
+                start_color: Color::rgb(0.6, 0.9, 1.0),
+                end_color: Color::rgba(0.2, 0.4, 1.0, 0.0),
+                start_scale: 0.04,
+                end_scale: 0.01,
+                dampening: 3.0,
+            },
+            ParticleEffect::Exhaust => EffectSpec {
+                count: 1,
+                budget: 150,
+                spread: PI / 10.0,
+                speed: (150.0, 250.0),
+                lifetime: (0.2, 0.4),
+                start_color: Color::rgb(1.0, 0.7, 0.3),
+                end_color: Color::rgba(1.0, 0.2, 0.1, 0.0),
+                start_scale: 0.05,
+                end_scale: 0.1,
+                dampening: 2.0,
+            },
+            ParticleEffect::Sparkle => EffectSpec {
+                count: 12,
+                budget: 100,
+                spread: PI,
+                speed: (20.0, 80.0),
+                lifetime: (0.3, 0.7),
+                start_color: Color::WHITE,
+                end_color: Color::rgba(1.0, 1.0, 1.0, 0.0),
+                start_scale: 0.03,
+                end_scale: 0.0,
+                dampening: 0.5,
+            },
+        }
+    }
+}
+
+/// Burst of particles.
+pub struct ParticleEvent {
+    pub effect: ParticleEffect,
+    pub position: Vec2,
+    /// Main direction of the particles.
+    pub direction: Vec2,
+    /// Speed of the source, added to the particles.
+    pub velocity: Vec2,
+    /// Multiplies the count and the scale of the particles.
+    pub size: f32,
+    /// Replaces the start color of the effect.
+    pub color: Option<Color>,
+}
+
+/// Continuous emission, while active.
+pub struct ParticleEmitter {
+    pub effect: ParticleEffect,
+    /// Particles per second.
+    pub rate: f32,
+    /// Set each frame by the emitter user, cleared after the emission.
+    pub active: bool,
+    /// Emission point and direction, in the entity frame.
+    pub offset: Vec2,
+    pub direction: Vec2,
+    accumulator: f32,
+}
+
+impl ParticleEmitter {
+    pub fn new(effect: ParticleEffect, rate: f32, offset: Vec2, direction: Vec2) -> Self {
+        ParticleEmitter {
+            effect,
+            rate,
+            active: false,
+            offset,
+            direction,
+            accumulator: 0.0,
+        }
+    }
+}
+
+pub struct Particle {
+    effect: ParticleEffect,
+    velocity: Vec2,
+    age: f32,
+    lifetime: f32,
+    start_color: Color,
+    size: f32,
+}
+
+/// Particles alive for each effect.
+#[derive(Default)]
+pub struct ParticleBudget {
+    alive: HashMap<ParticleEffect, usize>,
+}
+
+impl ParticleBudget {
+    fn total(&self) -> usize {
+        self.alive.values().sum()
+    }
+    /// Reserve up to count particles, returns how many can be spawned.
+    fn reserve(&mut self, effect: ParticleEffect, count: usize) -> usize {
+        let free_total = MAX_PARTICLES.saturating_sub(self.total());
+        let alive = self.alive.entry(effect).or_insert(0);
+        let count = count
+            .min(effect.spec().budget.saturating_sub(*alive))
+            .min(free_total);
+        *alive += count;
+        count
+    }
+    fn release(&mut self, effect: ParticleEffect) {
+        if let Some(alive) = self.alive.get_mut(&effect) {
+            *alive = alive.saturating_sub(1);
+        }
+    }
+}
+
+fn lerp_color(from: Color, to: Color, t: f32) -> Color {
+    Color::rgba(
+        from.r() + (to.r() - from.r()) * t,
+        from.g() + (to.g() - from.g()) * t,
+        from.b() + (to.b() - from.b()) * t,
+        from.a() + (to.a() - from.a()) * t,
+    )
+}
+
+fn spawn_particles(
+    commands: &mut Commands,
+    materials: &mut Assets<ColorMaterial>,
+    texture: &Handle<Texture>,
+    budget: &mut ParticleBudget,
+    event: &ParticleEvent,
+) {
+    let spec = event.effect.spec();
+    let wanted = ((spec.count as f32 * event.size).round() as usize).max(1);
+    let count = budget.reserve(event.effect, wanted);
+    let start_color = event.color.unwrap_or(spec.start_color);
+    let base_angle = event.direction.y().atan2(event.direction.x());
+    let mut rng = thread_rng();
+    for _ in 0..count {
+        let angle = base_angle + rng.gen_range(-spec.spread, spec.spread);
+        let speed = rng.gen_range(spec.speed.0, spec.speed.1);
+        let velocity = Vec2::new(angle.cos(), angle.sin()) * speed + event.velocity;
+        commands
+            .spawn(SpriteComponents {
+                material: materials.add(ColorMaterial::modulated_texture(
+                    texture.clone(),
+                    start_color,
+                )),
+                transform: Transform {
+                    translation: event.position.extend(5.0),
+                    scale: Vec3::splat(spec.start_scale * event.size),
+                    ..Default::default()
+                },
+                ..Default::default()
+            })
+            .with(Particle {
+                effect: event.effect,
+                velocity,
+                age: 0.0,
+                lifetime: rng.gen_range(spec.lifetime.0, spec.lifetime.1),
+                start_color,
+                size: event.size,
+            });
+    }
+}
+
+pub fn particle_emitter_system(
+    time: Res<Time>,
+    mut particle_events: ResMut<Events<ParticleEvent>>,
+    mut emitters: Query<(Mut<ParticleEmitter>, &Transform, Option<&Movement>)>,
+) {
+    for (mut emitter, transform, movement) in emitters.iter_mut() {
+        if !emitter.active {
+            emitter.accumulator = 0.0;
+            continue;
+        }
+        emitter.active = false;
+        emitter.accumulator += emitter.rate * time.delta_seconds;
+        let position = transform.translation + transform.rotation * emitter.offset.extend(0.0);
+        let direction = (transform.rotation * emitter.direction.extend(0.0)).truncate();
+        let speed = movement.map_or(Vec2::zero(), |movement| movement.speed);
+        while emitter.accumulator >= 1.0 {
+            emitter.accumulator -= 1.0;
+            particle_events.send(ParticleEvent {
+                effect: emitter.effect,
+                position: position.truncate(),
+                direction,
+                velocity: speed,
+                size: 1.0,
+                color: None,
+            });
+        }
+    }
+}
+
+pub fn particle_spawn_system(
+    mut commands: Commands,
+    mut events: Local<EventReader<ParticleEvent>>,
+    particle_events: Res<Events<ParticleEvent>>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut budget: ResMut<ParticleBudget>,
+) {
+    let texture = asset_server.load("sprite_sphere_256x256.png");
+    for event in events.iter(&particle_events) {
+        spawn_particles(&mut commands, &mut materials, &texture, &mut budget, event);
+    }
+}
+
+/// Move the particles, and fade them over their life.
+pub fn particle_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut budget: ResMut<ParticleBudget>,
+    mut particles: Query<(
+        Entity,
+        Mut<Particle>,
+        Mut<Transform>,
+        &Handle<ColorMaterial>,
+    )>,
+) {
+    let dt = time.delta_seconds;
+    for (entity, mut particle, mut transform, material) in particles.iter_mut() {
+        particle.age += dt;
+        if particle.age >= particle.lifetime {
+            budget.release(particle.effect);
+            commands.despawn(entity);
+            continue;
+        }
+        let spec = particle.effect.spec();
+        let t = particle.age / particle.lifetime;
+        let velocity = particle.velocity * (1.0 - spec.dampening * dt).max(0.0);
+        particle.velocity = velocity;
+        transform.translation += (velocity * dt).extend(0.0);
+        transform.scale = Vec3::splat(
+            (spec.start_scale + (spec.end_scale - spec.start_scale) * t) * particle.size,
+        );
+        if let Some(material) = materials.get_mut(material) {
+            material.color = lerp_color(particle.start_color, spec.end_color, t);
+        }
+    }
+}
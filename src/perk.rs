@@ -0,0 +1,292 @@
+use std::collections::HashSet;
+
+use super::*;
+
+/// Perks are unlocked on level up, forming a tree through their prerequisites.
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum Perk {
+    Afterburner,
+    AgileThrusters,
+    Gyroscopes,
+    RapidFire,
+    LongRange,
+    Overcharge,
+    ReinforcedHull,
+    NanoRepair,
+    Juggernaut,
+}
+
+impl Perk {
+    pub const ALL: [Perk; 9] = [
+        Perk::Afterburner,
+        Perk::AgileThrusters,
+        Perk::Gyroscopes,
+        Perk::RapidFire,
+        Perk::LongRange,
+        Perk::Overcharge,
+        Perk::ReinforcedHull,
+        Perk::NanoRepair,
+        Perk::Juggernaut,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Perk::Afterburner => "Afterburner: +15% speed",
+            Perk::AgileThrusters => "Agile thrusters: +25% lateral speed",
+            Perk::Gyroscopes => "Gyroscopes: +30% rotation speed",
+            Perk::RapidFire => "Rapid fire: +15% fire rate",
+            Perk::LongRange => "Long range: +30% munition lifespan",
+            Perk::Overcharge => "Overcharge: +1 damage",
+            Perk::ReinforcedHull => "Reinforced hull: +2 armor",
+            Perk::NanoRepair => "Nano repair: +1 armor and full repair",
+            Perk::Juggernaut => "Juggernaut: +3 armor, -10% speed",
+        }
+    }
+    /// Perks that must all be unlocked before this one is available.
+    pub fn prerequisites(&self) -> &'static [Perk] {
+        match self {
+            Perk::Gyroscopes => &[Perk::AgileThrusters],
+            Perk::LongRange => &[Perk::RapidFire],
+            Perk::Overcharge => &[Perk::RapidFire, Perk::LongRange],
+            Perk::NanoRepair => &[Perk::ReinforcedHull],
+            Perk::Juggernaut => &[Perk::ReinforcedHull, Perk::Afterburner],
+            _ => &[],
+        }
+    }
+    pub fn apply(&self, ship: &mut Spaceship, weapon: &mut Weapon, armor: &mut Armor) {
+        match self {
+            Perk::Afterburner => ship.max_linvel *= 1.15,
+            Perk::AgileThrusters => ship.max_latvel *= 1.25,
+            Perk::Gyroscopes => {
+                ship.max_angvel *= 1.3;
+                ship.max_angacc *= 1.3;
+            }
+            Perk::RapidFire => weapon.fire_timer.duration *= 0.85,
+            Perk::LongRange => weapon.munition_lifespan *= 1.3,
+            Perk::Overcharge => weapon.damage += 1,
+            Perk::ReinforcedHull => {
+                armor.max_life += 2;
+                armor.life += 2;
+            }
+            Perk::NanoRepair => {
+                armor.max_life += 1;
+                armor.life = armor.max_life;
+            }
+            Perk::Juggernaut => {
+                armor.max_life += 3;
+                armor.life += 3;
+                ship.max_linvel *= 0.9;
+            }
+        }
+    }
+}
+
+/// Perks of a ship, and the number of choices still to be made.
+#[derive(Default)]
+pub struct Perks {
+    pub unlocked: HashSet<Perk>,
+    pub pending: u32,
+}
+
+impl Perks {
+    /// Perks not yet unlocked whose prerequisites are all unlocked.
+    pub fn available(&self) -> Vec<Perk> {
+        Perk::ALL
+            .iter()
+            .filter(|perk| {
+                !self.unlocked.contains(perk)
+                    && perk
+                        .prerequisites()
+                        .iter()
+                        .all(|prerequisite| self.unlocked.contains(prerequisite))
+            })
+            .cloned()
+            .collect()
+    }
+}
+
+const PERK_CHOICES: usize = 3;
+const PERK_KEYS: [KeyCode; PERK_CHOICES] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
+
+/// Perks offered in GameState::LevelUp.
+#[derive(Default)]
+pub struct PerkChoice {
+    pub ship: Option<Entity>,
+    pub options: Vec<Perk>,
+    /// Option highlighted for the gamepad, chosen with the south button.
+    pub cursor: usize,
+}
+
+/// Count pending perks on level up, and open the choice while playing.
+/// Networked clients have no perk controls, their perks are drawn without pausing the server.
+pub fn level_up_system(
+    mut level_up_reader: Local<EventReader<LevelUpEvent>>,
+    level_up_events: Res<Events<LevelUpEvent>>,
+    mut state: ResMut<GameState>,
+    mut choice: ResMut<PerkChoice>,
+    mut perks: Query<With<UserControlled, (Entity, &InputDevice, Mut<Perks>)>>,
+    mut ships: Query<(Mut<Spaceship>, Mut<Weapon>, Mut<Armor>)>,
+) {
+    for event in level_up_reader.iter(&level_up_events) {
+        if let Ok(mut ship_perks) = perks.get_component_mut::<Perks>(event.entity) {
+            ship_perks.pending += 1;
+        }
+    }
+    for (entity, device, mut ship_perks) in perks.iter_mut() {
+        if *device != InputDevice::Remote {
+            continue;
+        }
+        while ship_perks.pending > 0 {
+            ship_perks.pending -= 1;
+            // Nothing is drawn once the whole tree is unlocked.
+            let perk = ship_perks.available().choose(&mut thread_rng()).copied();
+            if let (Some(perk), Ok((mut spaceship, mut weapon, mut armor))) =
+                (perk, ships.get_mut(entity))
+            {
+                ship_perks.unlocked.insert(perk);
+                perk.apply(&mut spaceship, &mut weapon, &mut armor);
+            }
+        }
+    }
+    if *state != GameState::Playing {
+        return;
+    }
+    for (entity, device, mut ship_perks) in perks.iter_mut() {
+        if ship_perks.pending == 0 || *device == InputDevice::Remote {
+            continue;
+        }
+        let available = ship_perks.available();
+        if available.is_empty() {
+            // Whole tree unlocked, nothing left to offer.
+            ship_perks.pending = 0;
+            continue;
+        }
+        let mut rng = thread_rng();
+        choice.ship = Some(entity);
+        choice.cursor = 0;
+        choice.options = available
+            .choose_multiple(&mut rng, PERK_CHOICES)
+            .cloned()
+            .collect();
+        *state = GameState::LevelUp;
+        return;
+    }
+}
+
+/// Unlock the perk chosen by the player of the ship with their own device: number keys,
+/// or the d-pad and south button of the gamepad.
+pub fn perk_choice_system(
+    mut state: ResMut<GameState>,
+    mut choice: ResMut<PerkChoice>,
+    keyboard_input: Res<Input<KeyCode>>,
+    (gamepad_state, gamepad_buttons): (Res<GamepadState>, Res<Input<GamepadButton>>),
+    mut ships: Query<(
+        &InputDevice,
+        Mut<Perks>,
+        Mut<Spaceship>,
+        Mut<Weapon>,
+        Mut<Armor>,
+    )>,
+) {
+    if *state != GameState::LevelUp {
+        return;
+    }
+    let ship = match choice.ship {
+        Some(ship) => ship,
+        None => return,
+    };
+    let (keyboard, gamepad) = match ships.get_component::<InputDevice>(ship) {
+        Ok(InputDevice::Auto) => (true, true),
+        Ok(InputDevice::KeyboardMouse) => (true, false),
+        Ok(InputDevice::Gamepad) => (false, true),
+        // Networked clients have no perk controls.
+        Ok(InputDevice::Remote) | Err(_) => (false, false),
+    };
+    let gamepad_pressed =
+        |button_type| gamepad && gamepad_state.just_pressed(&gamepad_buttons, button_type);
+    let count = choice.options.len();
+    if count > 0 && gamepad_pressed(GamepadButtonType::DPadDown) {
+        choice.cursor = (choice.cursor + 1) % count;
+    }
+    if count > 0 && gamepad_pressed(GamepadButtonType::DPadUp) {
+        choice.cursor = (choice.cursor + count - 1) % count;
+    }
+    let keyboard_perk = PERK_KEYS
+        .iter()
+        .zip(choice.options.iter())
+        .find(|(key, _)| keyboard && keyboard_input.just_pressed(**key))
+        .map(|(_, &perk)| perk);
+    let gamepad_perk = if gamepad_pressed(GamepadButtonType::South) {
+        choice.options.get(choice.cursor).copied()
+    } else {
+        None
+    };
+    if let Some(perk) = keyboard_perk.or(gamepad_perk) {
+        if let Ok((_, mut perks, mut spaceship, mut weapon, mut armor)) = ships.get_mut(ship) {
+            perks.unlocked.insert(perk);
+            perks.pending -= 1;
+            perk.apply(&mut spaceship, &mut weapon, &mut armor);
+        }
+        choice.ship = None;
+        choice.options.clear();
+        // level_up_system opens the next choice if more are pending.
+        *state = GameState::Playing;
+    }
+}
+
+/// Tag component for the perk choice panel root node.
+pub struct PerkPanel;
+/// Tag component for the text of one perk choice, None for the title.
+pub struct PerkText(Option<usize>);
+
+/// Show the perk choice panel while in GameState::LevelUp, naming the player in co-op.
+pub fn perk_ui_system(
+    mut commands: Commands,
+    state: Res<GameState>,
+    choice: Res<PerkChoice>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    (local_players, gamepad_state): (Res<LocalPlayers>, Res<GamepadState>),
+    panels: Query<With<PerkPanel, Entity>>,
+    ships: Query<(&LocalPlayer, &InputDevice)>,
+    mut texts: Query<(&PerkText, Mut<Text>)>,
+) {
+    let panel = panels.iter().next();
+    if *state != GameState::LevelUp {
+        if let Some(entity) = panel {
+            commands.despawn_recursive(entity);
+        }
+        return;
+    }
+    if panel.is_none() {
+        let lines = std::iter::once(None)
+            .chain((0..choice.options.len()).map(Some))
+            .map(PerkText)
+            .collect();
+        let font = asset_server.load("FiraSans-Bold.ttf");
+        spawn_panel(&mut commands, &mut materials, font, PerkPanel, lines);
+        return;
+    }
+    let ship = choice.ship.and_then(|ship| ships.get(ship).ok());
+    let gamepad_choosing = ship.map_or(false, |(_, device)| device.uses_gamepad(&gamepad_state));
+    for (line, mut text) in texts.iter_mut() {
+        text.value = match line.0 {
+            None => match ship {
+                Some((player, _)) if local_players.0 > 1 => {
+                    format!("Level up! P{}, choose a perk", player.0 + 1)
+                }
+                _ => "Level up! Choose a perk".to_string(),
+            },
+            Some(index) => match choice.options.get(index) {
+                Some(perk) => {
+                    let marker = if gamepad_choosing && index == choice.cursor {
+                        "> "
+                    } else {
+                        ""
+                    };
+                    format!("{}{}: {}", marker, index + 1, perk.name())
+                }
+                None => "".to_string(),
+            },
+        };
+    }
+}
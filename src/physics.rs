@@ -0,0 +1,76 @@
+use super::*;
+
+/// Share of the penetration resolved each frame, to push overlapping bodies apart.
+const POSITION_CORRECTION: f32 = 0.8;
+
+/// Contact between two bodies, normal going from e1 to e2.
+pub struct ImpactEvent {
+    pub e1: Entity,
+    pub e2: Entity,
+    pub normal: Vec2,
+    pub depth: f32,
+}
+
+/// Coefficient of restitution, 1.0 for an elastic collision, 0.0 for a perfectly inelastic one.
+fn restitution(t1: ColliderType, t2: ColliderType) -> Option<f32> {
+    match (t1, t2) {
+        (ColliderType::Enemy, ColliderType::Enemy) => Some(0.9),
+        (ColliderType::Ship, ColliderType::Enemy) | (ColliderType::Enemy, ColliderType::Ship) => {
+            Some(0.4)
+        }
+        // The missile is destroyed, all of its momentum goes to the enemy.
+        (ColliderType::Missile, ColliderType::Enemy)
+        | (ColliderType::Enemy, ColliderType::Missile) => Some(0.0),
+        _ => None,
+    }
+}
+
+/// Apply collision impulses to bodies in contact.
+pub fn impact_system(
+    mut impact_reader: Local<EventReader<ImpactEvent>>,
+    impact_events: Res<Events<ImpactEvent>>,
+    collider_types: Query<&ColliderType>,
+    mut bodies: Query<(Mut<Movement>, Mut<Transform>)>,
+) {
+    for event in impact_reader.iter(&impact_events) {
+        let restitution = match (
+            collider_types.get_component::<ColliderType>(event.e1),
+            collider_types.get_component::<ColliderType>(event.e2),
+        ) {
+            (Ok(&t1), Ok(&t2)) => match restitution(t1, t2) {
+                Some(restitution) => restitution,
+                None => continue,
+            },
+            _ => continue,
+        };
+        let (v1, m1) = match bodies.get_component::<Movement>(event.e1) {
+            Ok(movement) => (movement.speed, movement.mass),
+            Err(_) => continue,
+        };
+        let (v2, m2) = match bodies.get_component::<Movement>(event.e2) {
+            Ok(movement) => (movement.speed, movement.mass),
+            Err(_) => continue,
+        };
+        let inv_m1 = if m1 > 0.0 { 1.0 / m1 } else { 0.0 };
+        let inv_m2 = if m2 > 0.0 { 1.0 / m2 } else { 0.0 };
+        if inv_m1 + inv_m2 == 0.0 {
+            continue;
+        }
+        // Only bodies getting closer exchange momentum.
+        let approach_speed = (v1 - v2).dot(event.normal);
+        let impulse = if approach_speed > 0.0 {
+            (1.0 + restitution) * approach_speed / (inv_m1 + inv_m2)
+        } else {
+            0.0
+        };
+        let correction = event.depth * POSITION_CORRECTION / (inv_m1 + inv_m2);
+        if let Ok((mut movement, mut transform)) = bodies.get_mut(event.e1) {
+            movement.speed -= event.normal * impulse * inv_m1;
+            transform.translation -= (event.normal * correction * inv_m1).extend(0.0);
+        }
+        if let Ok((mut movement, mut transform)) = bodies.get_mut(event.e2) {
+            movement.speed += event.normal * impulse * inv_m2;
+            transform.translation += (event.normal * correction * inv_m2).extend(0.0);
+        }
+    }
+}
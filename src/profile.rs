@@ -0,0 +1,358 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use super::*;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// Bumped each time the profile format changes, see Profile::migrate.
+const PROFILE_VERSION: u32 = 1;
+const PROFILE_FILE: &str = "profile.ron";
+
+/// Path of a file in the user data directory, None if the platform has none.
+fn user_data_path(file: &str) -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("kotlot").join(file))
+}
+
+/// Write through a temporary file, so a crash can't leave a truncated file.
+fn write_user_data(path: &Path, content: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, content)?;
+    fs::rename(&tmp_path, path)
+}
+
+/// Move an unreadable file aside, so it is not overwritten and can be inspected.
+fn backup_corrupt_user_data(path: &Path) {
+    let backup_path = path.with_extension("corrupt");
+    match fs::rename(path, &backup_path) {
+        Ok(()) => println!("Moved corrupt {:?} to {:?}", path, backup_path),
+        Err(e) => println!("Failed to move corrupt {:?} aside: {}", path, e),
+    }
+}
+
+/// Load a file of the user data directory.
+/// A missing or corrupt file gives the default value.
+pub fn load_user_data<T: DeserializeOwned + Default>(file: &str) -> T {
+    match user_data_path(file) {
+        Some(path) => load_user_data_from(&path),
+        None => T::default(),
+    }
+}
+
+fn load_user_data_from<T: DeserializeOwned + Default>(path: &Path) -> T {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return T::default(),
+    };
+    match ron::de::from_str(&content) {
+        Ok(data) => data,
+        Err(e) => {
+            println!("Failed to read {:?}: {}", path, e);
+            backup_corrupt_user_data(path);
+            T::default()
+        }
+    }
+}
+
+pub fn save_user_data<T: Serialize>(file: &str, data: &T) {
+    if let Some(path) = user_data_path(file) {
+        save_user_data_to(&path, data);
+    }
+}
+
+fn save_user_data_to<T: Serialize>(path: &Path, data: &T) {
+    let result = ron::ser::to_string_pretty(data, ron::ser::PrettyConfig::default())
+        .map_err(|e| e.to_string())
+        .and_then(|content| write_user_data(path, &content).map_err(|e| e.to_string()));
+    if let Err(e) = result {
+        println!("Failed to save {:?}: {}", path, e);
+    }
+}
+
+/// Statistics of the player kept across runs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Profile {
+    pub version: u32,
+    pub best_score: u32,
+    pub highest_wave: u32,
+    pub total_kills: u32,
+    pub unlocked_ships: Vec<ShipClass>,
+    pub selected_ship: ShipClass,
+    pub meta_currency: u32,
+    /// Set when the file comes from a newer version, so it is not overwritten.
+    #[serde(skip)]
+    pub read_only: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile {
+            version: PROFILE_VERSION,
+            best_score: 0,
+            highest_wave: 0,
+            total_kills: 0,
+            unlocked_ships: vec![ShipClass::Fighter],
+            selected_ship: ShipClass::Fighter,
+            meta_currency: 0,
+            read_only: false,
+        }
+    }
+}
+
+impl Profile {
+    /// Load the profile from the user data directory.
+    /// A missing or corrupt file gives a fresh profile.
+    pub fn load() -> Profile {
+        match user_data_path(PROFILE_FILE) {
+            Some(path) => Profile::load_from(&path),
+            None => Profile::default(),
+        }
+    }
+    fn load_from(path: &Path) -> Profile {
+        load_user_data_from::<Profile>(path).migrate()
+    }
+    pub fn save(&self) {
+        if let Some(path) = user_data_path(PROFILE_FILE) {
+            self.save_to(&path);
+        }
+    }
+    fn save_to(&self, path: &Path) {
+        if self.read_only {
+            println!("Profile {:?} is from a newer version, not saved", path);
+            return;
+        }
+        save_user_data_to(path, self);
+    }
+    /// Bring a profile from an older format to the current one.
+    /// A profile from a newer version is kept as is and never saved, its unknown fields
+    /// would be lost.
+    fn migrate(mut self) -> Profile {
+        if self.version > PROFILE_VERSION {
+            println!(
+                "Profile version {} is newer than {}, it won't be saved",
+                self.version, PROFILE_VERSION
+            );
+            self.read_only = true;
+        } else {
+            // Version 1 is the first format, fields added later default through serde.
+            self.version = PROFILE_VERSION;
+        }
+        if !self.unlocked_ships.contains(&ShipClass::Fighter) {
+            self.unlocked_ships.insert(0, ShipClass::Fighter);
+        }
+        if !self.unlocked_ships.contains(&self.selected_ship) {
+            self.selected_ship = ShipClass::Fighter;
+        }
+        self
+    }
+    /// Select the ship given with --ship, saved for the next runs.
+    pub fn select_from_args(&mut self) {
+        let name = match arg_value("--ship") {
+            Some(name) => name,
+            None => return,
+        };
+        match ShipClass::from_name(&name) {
+            Some(ship_class) => {
+                if ship_class != self.selected_ship && self.select_ship(ship_class) {
+                    self.save();
+                }
+            }
+            None => println!(
+                "Unknown ship {}, expected fighter, interceptor or gunship",
+                name
+            ),
+        }
+    }
+    /// Select a ship, unlocking it with meta currency if its wave was never reached.
+    /// False when it is locked and can't be paid for.
+    fn select_ship(&mut self, ship_class: ShipClass) -> bool {
+        if !self.unlocked_ships.contains(&ship_class) {
+            let cost = ship_class.unlock_cost();
+            if self.meta_currency < cost {
+                println!(
+                    "{:?} is locked: reach wave {} or spend {} meta credits, {} available",
+                    ship_class,
+                    ship_class.unlock_wave(),
+                    cost,
+                    self.meta_currency
+                );
+                return false;
+            }
+            self.meta_currency -= cost;
+            self.unlocked_ships.push(ship_class);
+            println!("{:?} unlocked for {} meta credits", ship_class, cost);
+        }
+        self.selected_ship = ship_class;
+        true
+    }
+    /// Merge the results of a finished run.
+    pub fn record_run(&mut self, run: &RunStats, wave: u32) {
+        self.best_score = self.best_score.max(run.score);
+        self.highest_wave = self.highest_wave.max(wave);
+        self.total_kills += run.kills;
+        self.meta_currency += run.score / 10;
+        for &ship_class in ShipClass::ALL.iter() {
+            if ship_class.unlock_wave() <= self.highest_wave
+                && !self.unlocked_ships.contains(&ship_class)
+            {
+                println!("{:?} unlocked", ship_class);
+                self.unlocked_ships.push(ship_class);
+            }
+        }
+    }
+}
+
+/// Update and save the profile once, when the game is over.
+pub fn profile_game_over_system(
+    mut recorded: Local<bool>,
+    state: Res<GameState>,
+    run: Res<RunStats>,
+    wave: Res<Wave>,
+    mut profile: ResMut<Profile>,
+) {
+    if *state != GameState::GameOver || *recorded {
+        return;
+    }
+    *recorded = true;
+    profile.record_run(&run, wave.number);
+    profile.save();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Fresh path in a directory private to the test process.
+    fn test_path(file: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kotlot-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(file);
+        let _ = fs::remove_file(&path);
+        let _ = fs::remove_file(path.with_extension("corrupt"));
+        path
+    }
+
+    #[test]
+    fn migrate_keeps_the_fighter_unlocked() {
+        let profile = Profile {
+            version: 0,
+            unlocked_ships: vec![ShipClass::Gunship],
+            selected_ship: ShipClass::Gunship,
+            ..Profile::default()
+        }
+        .migrate();
+        assert_eq!(profile.version, PROFILE_VERSION);
+        assert_eq!(
+            profile.unlocked_ships,
+            vec![ShipClass::Fighter, ShipClass::Gunship]
+        );
+        assert_eq!(profile.selected_ship, ShipClass::Gunship);
+        assert!(!profile.read_only);
+    }
+
+    #[test]
+    fn migrate_resets_a_locked_selection() {
+        let profile = Profile {
+            selected_ship: ShipClass::Interceptor,
+            ..Profile::default()
+        }
+        .migrate();
+        assert_eq!(profile.selected_ship, ShipClass::Fighter);
+    }
+
+    #[test]
+    fn select_unlocked_ship_is_free() {
+        let mut profile = Profile {
+            unlocked_ships: vec![ShipClass::Fighter, ShipClass::Interceptor],
+            meta_currency: 1000,
+            ..Profile::default()
+        };
+        assert!(profile.select_ship(ShipClass::Interceptor));
+        assert_eq!(profile.selected_ship, ShipClass::Interceptor);
+        assert_eq!(profile.meta_currency, 1000);
+    }
+
+    #[test]
+    fn select_locked_ship_spends_meta_currency() {
+        let cost = ShipClass::Gunship.unlock_cost();
+        let mut profile = Profile {
+            meta_currency: cost - 1,
+            ..Profile::default()
+        };
+        assert!(!profile.select_ship(ShipClass::Gunship));
+        assert_eq!(profile.selected_ship, ShipClass::Fighter);
+        assert_eq!(profile.meta_currency, cost - 1);
+        profile.meta_currency = cost + 10;
+        assert!(profile.select_ship(ShipClass::Gunship));
+        assert_eq!(profile.selected_ship, ShipClass::Gunship);
+        assert_eq!(profile.meta_currency, 10);
+        assert!(profile.unlocked_ships.contains(&ShipClass::Gunship));
+    }
+
+    #[test]
+    fn newer_profile_is_never_saved() {
+        let path = test_path("newer.ron");
+        let newer = format!("(version: {}, best_score: 42)", PROFILE_VERSION + 1);
+        fs::write(&path, &newer).unwrap();
+        let mut profile = Profile::load_from(&path);
+        assert_eq!(profile.version, PROFILE_VERSION + 1);
+        assert_eq!(profile.best_score, 42);
+        assert!(profile.read_only);
+        profile.best_score = 100;
+        profile.save_to(&path);
+        assert_eq!(fs::read_to_string(&path).unwrap(), newer);
+    }
+
+    #[test]
+    fn missing_fields_take_their_default() {
+        let path = test_path("partial.ron");
+        fs::write(&path, "(version: 1, total_kills: 7)").unwrap();
+        let profile = Profile::load_from(&path);
+        assert_eq!(profile.total_kills, 7);
+        assert_eq!(profile.unlocked_ships, vec![ShipClass::Fighter]);
+    }
+
+    #[test]
+    fn saved_profile_loads_back() {
+        let path = test_path("saved.ron");
+        let profile = Profile {
+            best_score: 1200,
+            highest_wave: 6,
+            unlocked_ships: vec![ShipClass::Fighter, ShipClass::Interceptor],
+            selected_ship: ShipClass::Interceptor,
+            ..Profile::default()
+        };
+        profile.save_to(&path);
+        let loaded = Profile::load_from(&path);
+        assert_eq!(loaded.best_score, 1200);
+        assert_eq!(loaded.highest_wave, 6);
+        assert_eq!(loaded.selected_ship, ShipClass::Interceptor);
+        assert!(!path.with_extension("tmp").exists());
+    }
+
+    #[test]
+    fn corrupt_profile_is_moved_aside() {
+        let path = test_path("corrupt.ron");
+        fs::write(&path, "not a profile").unwrap();
+        let profile = Profile::load_from(&path);
+        assert_eq!(profile.best_score, 0);
+        assert!(!path.exists());
+        assert_eq!(
+            fs::read_to_string(path.with_extension("corrupt")).unwrap(),
+            "not a profile"
+        );
+    }
+
+    #[test]
+    fn missing_profile_is_fresh() {
+        let path = test_path("missing.ron");
+        let profile = Profile::load_from(&path);
+        assert_eq!(profile.version, PROFILE_VERSION);
+        assert!(!path.with_extension("corrupt").exists());
+    }
+}
@@ -0,0 +1,293 @@
+use super::*;
+
+pub struct XpEvent {
+    pub xp: u32,
+    pub source: Entity,
+}
+
+pub struct LevelUpEvent {
+    pub entity: Entity,
+}
+
+/// Xp needed per level, as a geometric progression.
+#[derive(Clone, Debug)]
+pub struct XpCurve {
+    /// Xp needed to go from level 1 to level 2.
+    pub base: f32,
+    /// Factor applied to the xp needed at each new level.
+    pub growth: f32,
+    /// Last reachable level, None for an uncapped progression.
+    pub max_level: Option<u32>,
+    /// Xp needed per prestige, once max_level is reached.
+    pub prestige_xp: u32,
+}
+
+impl Default for XpCurve {
+    fn default() -> XpCurve {
+        XpCurve {
+            base: 10.0,
+            growth: 2.0,
+            max_level: Some(12),
+            prestige_xp: 10000,
+        }
+    }
+}
+
+impl XpCurve {
+    /// Xp needed to go from level to level + 1, None when level is the max level.
+    pub fn xp_to_next(&self, level: u32) -> Option<u32> {
+        if self.max_level.map_or(false, |max_level| level >= max_level) {
+            return None;
+        }
+        let xp = self.base * self.growth.powi(level as i32 - 1);
+        Some((xp.round() as u32).max(1))
+    }
+}
+
+#[derive(Default, Eq, PartialEq, Copy, Clone, Debug)]
+pub struct XpGain {
+    pub levels: u32,
+    pub prestiges: u32,
+}
+
+#[derive(Debug)]
+pub struct Progression {
+    pub level: u32,
+    /// Xp toward the next level, or toward the next prestige at max level.
+    pub xp: u32,
+    pub prestige: u32,
+}
+
+impl Progression {
+    pub fn new() -> Progression {
+        Progression {
+            level: 1,
+            xp: 0,
+            prestige: 0,
+        }
+    }
+    /// Add xp, possibly crossing several levels.
+    /// Once at max level, xp overflows into prestige.
+    pub fn add_xp(&mut self, xp: u32, curve: &XpCurve) -> XpGain {
+        let mut gain = XpGain::default();
+        self.xp = self.xp.saturating_add(xp);
+        while let Some(needed) = curve.xp_to_next(self.level) {
+            if self.xp < needed {
+                return gain;
+            }
+            self.xp -= needed;
+            self.level += 1;
+            gain.levels += 1;
+        }
+        if curve.prestige_xp > 0 {
+            gain.prestiges = self.xp / curve.prestige_xp;
+            self.prestige += gain.prestiges;
+            self.xp %= curve.prestige_xp;
+        }
+        gain
+    }
+    /// Xp needed for the next level, or for the next prestige at max level.
+    /// None at max level without prestige, there is nothing left to reach.
+    pub fn xp_needed(&self, curve: &XpCurve) -> Option<u32> {
+        curve
+            .xp_to_next(self.level)
+            .or_else(|| Some(curve.prestige_xp).filter(|&xp| xp > 0))
+    }
+}
+
+pub fn xp_system(
+    mut xp_event_reader: Local<EventReader<XpEvent>>,
+    xp_events: Res<Events<XpEvent>>,
+    curve: Res<XpCurve>,
+    mut level_up_events: ResMut<Events<LevelUpEvent>>,
+    mut progressions: Query<Mut<Progression>>,
+) {
+    for event in xp_event_reader.iter(&*xp_events) {
+        if let Ok(mut progression) = progressions.get_component_mut::<Progression>(event.source) {
+            let gain = progression.add_xp(event.xp, &curve);
+            for _ in 0..gain.levels {
+                level_up_events.send(LevelUpEvent {
+                    entity: event.source,
+                });
+            }
+        }
+    }
+}
+
+/// Tag component for the text showing the level of a player ship.
+pub struct ProgressionText;
+
+/// Spawn the level text of each local player, above their inventory.
+pub fn setup_progression_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    local_players: Res<LocalPlayers>,
+) {
+    let font = asset_server.load("FiraSans-Bold.ttf");
+    // Above the player name in co-op.
+    let bottom = if local_players.0 > 1 { 130.0 } else { 100.0 };
+    for player in (0..local_players.0).map(LocalPlayer) {
+        commands
+            .spawn(TextComponents {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: hud_position(player, bottom),
+                    ..Default::default()
+                },
+                text: Text {
+                    value: "".to_string(),
+                    font: font.clone(),
+                    style: TextStyle {
+                        font_size: 24.0,
+                        color: Color::rgb(0.8, 0.8, 0.9),
+                    },
+                },
+                ..Default::default()
+            })
+            .with(ProgressionText)
+            .with(player);
+    }
+}
+
+pub fn progression_ui_system(
+    curve: Res<XpCurve>,
+    progressions: Query<With<UserControlled, (&LocalPlayer, Changed<Progression>)>>,
+    mut texts: Query<With<ProgressionText, (&LocalPlayer, Mut<Text>)>>,
+) {
+    for (player, progression) in progressions.iter() {
+        for (text_player, mut text) in texts.iter_mut() {
+            if text_player != player {
+                continue;
+            }
+            let level = match progression.xp_needed(&curve) {
+                Some(needed) => format!(
+                    "Level {}  Xp: {}/{}",
+                    progression.level, progression.xp, needed
+                ),
+                None => format!("Level {}  MAX", progression.level),
+            };
+            text.value = if progression.prestige > 0 {
+                format!("{}  Prestige {}", level, progression.prestige)
+            } else {
+                level
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capped_curve() -> XpCurve {
+        XpCurve {
+            base: 10.0,
+            growth: 2.0,
+            max_level: Some(4),
+            prestige_xp: 100,
+        }
+    }
+
+    #[test]
+    fn xp_to_next_follows_the_curve() {
+        let curve = capped_curve();
+        assert_eq!(curve.xp_to_next(1), Some(10));
+        assert_eq!(curve.xp_to_next(2), Some(20));
+        assert_eq!(curve.xp_to_next(3), Some(40));
+        assert_eq!(curve.xp_to_next(4), None);
+        assert_eq!(curve.xp_to_next(5), None);
+    }
+
+    #[test]
+    fn xp_to_next_is_never_zero() {
+        let curve = XpCurve {
+            base: 0.0,
+            ..capped_curve()
+        };
+        assert_eq!(curve.xp_to_next(1), Some(1));
+    }
+
+    #[test]
+    fn level_up_on_exact_threshold() {
+        let curve = capped_curve();
+        let mut progression = Progression::new();
+        assert_eq!(progression.add_xp(9, &curve), XpGain::default());
+        assert_eq!((progression.level, progression.xp), (1, 9));
+        let gain = progression.add_xp(1, &curve);
+        assert_eq!(gain.levels, 1);
+        assert_eq!((progression.level, progression.xp), (2, 0));
+    }
+
+    #[test]
+    fn large_gain_crosses_several_levels() {
+        let curve = capped_curve();
+        let mut progression = Progression::new();
+        let gain = progression.add_xp(35, &curve);
+        assert_eq!(gain.levels, 2);
+        assert_eq!((progression.level, progression.xp), (3, 5));
+    }
+
+    #[test]
+    fn xp_overflows_into_prestige_at_max_level() {
+        let curve = capped_curve();
+        let mut progression = Progression::new();
+        // 10 + 20 + 40 to reach level 4, then 250 of overflow.
+        let gain = progression.add_xp(320, &curve);
+        assert_eq!(
+            gain,
+            XpGain {
+                levels: 3,
+                prestiges: 2
+            }
+        );
+        assert_eq!(progression.level, 4);
+        assert_eq!(progression.prestige, 2);
+        assert_eq!(progression.xp, 50);
+        assert_eq!(progression.xp_needed(&curve), Some(100));
+        let gain = progression.add_xp(50, &curve);
+        assert_eq!(gain.levels, 0);
+        assert_eq!(gain.prestiges, 1);
+        assert_eq!((progression.prestige, progression.xp), (3, 0));
+    }
+
+    #[test]
+    fn no_prestige_without_prestige_xp() {
+        let curve = XpCurve {
+            prestige_xp: 0,
+            ..capped_curve()
+        };
+        let mut progression = Progression::new();
+        progression.add_xp(1000, &curve);
+        assert_eq!(progression.level, 4);
+        assert_eq!(progression.prestige, 0);
+        assert_eq!(progression.xp, 930);
+        assert_eq!(progression.xp_needed(&curve), None);
+    }
+
+    #[test]
+    fn uncapped_curve_keeps_levelling() {
+        let curve = XpCurve {
+            base: 10.0,
+            growth: 1.0,
+            max_level: None,
+            prestige_xp: 100,
+        };
+        let mut progression = Progression::new();
+        let gain = progression.add_xp(10005, &curve);
+        assert_eq!(gain.levels, 1000);
+        assert_eq!(gain.prestiges, 0);
+        assert_eq!((progression.level, progression.xp), (1001, 5));
+    }
+
+    #[test]
+    fn xp_does_not_overflow() {
+        let curve = XpCurve {
+            prestige_xp: 0,
+            ..capped_curve()
+        };
+        let mut progression = Progression::new();
+        progression.add_xp(u32::MAX, &curve);
+        progression.add_xp(u32::MAX, &curve);
+        assert_eq!(progression.xp, u32::MAX);
+    }
+}
@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+
+use super::*;
+
+/// Width of the radar, its height follows the arena ratio.
+const RADAR_WIDTH: f32 = 200.0;
+const RADAR_MARGIN: f32 = 10.0;
+const BLIP_SIZE: f32 = 4.0;
+const INDICATOR_SIZE: f32 = 10.0;
+/// Distance kept between off-screen indicators and the window edges.
+const INDICATOR_MARGIN: f32 = 15.0;
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum BlipKind {
+    Player,
+    WaveEnemy,
+    Asteroid,
+    Loot,
+    GravityWell,
+}
+
+/// Materials of the radar, created once.
+pub struct RadarMaterials {
+    background: Handle<ColorMaterial>,
+    viewport: Handle<ColorMaterial>,
+    blips: HashMap<BlipKind, Handle<ColorMaterial>>,
+}
+
+/// Tag component for the radar root node.
+pub struct Radar;
+/// Tag component for the rectangle of the area shown by the camera.
+pub struct RadarViewport;
+pub struct RadarBlip {
+    pub target: Entity,
+}
+/// Screen edge marker pointing toward an off-screen enemy.
+pub struct OffscreenIndicator {
+    pub target: Entity,
+}
+
+fn absolute_node(material: Handle<ColorMaterial>, size: Vec2) -> NodeComponents {
+    NodeComponents {
+        style: Style {
+            position_type: PositionType::Absolute,
+            size: Size::new(Val::Px(size.x()), Val::Px(size.y())),
+            ..Default::default()
+        },
+        material,
+        ..Default::default()
+    }
+}
+
+fn set_position(style: &mut Style, position: Vec2) {
+    style.position.left = Val::Px(position.x());
+    style.position.bottom = Val::Px(position.y());
+}
+
+pub fn setup_radar(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    let mut blips = HashMap::new();
+    blips.insert(
+        BlipKind::Player,
+        materials.add(Color::rgb(0.2, 1.0, 0.2).into()),
+    );
+    blips.insert(
+        BlipKind::WaveEnemy,
+        materials.add(Color::rgb(1.0, 0.2, 0.2).into()),
+    );
+    blips.insert(
+        BlipKind::Asteroid,
+        materials.add(Color::rgb(0.6, 0.6, 0.6).into()),
+    );
+    blips.insert(
+        BlipKind::Loot,
+        materials.add(Color::rgb(1.0, 0.85, 0.2).into()),
+    );
+    blips.insert(
+        BlipKind::GravityWell,
+        materials.add(Color::rgb(0.7, 0.3, 1.0).into()),
+    );
+    let radar_materials = RadarMaterials {
+        background: materials.add(Color::rgba(0.1, 0.1, 0.2, 0.6).into()),
+        viewport: materials.add(Color::rgba(1.0, 1.0, 1.0, 0.15).into()),
+        blips,
+    };
+    let radar_size = Vec2::new(RADAR_WIDTH, RADAR_WIDTH * arena.size.y() / arena.size.x());
+    let mut radar = absolute_node(radar_materials.background.clone(), radar_size);
+    radar.style.position = Rect {
+        right: Val::Px(RADAR_MARGIN),
+        top: Val::Px(RADAR_MARGIN),
+        ..Default::default()
+    };
+    commands.spawn(radar).with(Radar).with_children(|parent| {
+        parent
+            .spawn(absolute_node(
+                radar_materials.viewport.clone(),
+                Vec2::zero(),
+            ))
+            .with(RadarViewport);
+    });
+    commands.insert_resource(radar_materials);
+}
+
+/// Position on the radar, from the bottom left corner.
+/// The radar is centered on the player in a wrapping arena, so that nothing is cut by the edges.
+fn radar_position(arena: &Arena, center: Vec2, position: Vec2) -> Vec2 {
+    let radar_size = Vec2::new(RADAR_WIDTH, RADAR_WIDTH * arena.size.y() / arena.size.x());
+    let delta = arena.wrapped_delta(center, position);
+    (delta / arena.size + Vec2::splat(0.5)) * radar_size
+}
+
+pub fn radar_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    radar_materials: Res<RadarMaterials>,
+    radars: Query<With<Radar, Entity>>,
+    players: Query<With<UserControlled, (Entity, &Transform)>>,
+    enemies: Query<With<Enemy, (Entity, &Transform, Option<&WaveMember>)>>,
+    loots: Query<With<Loot, (Entity, &Transform)>>,
+    wells: Query<With<GravityWell, (Entity, &Transform)>>,
+    mut blips: Query<(Entity, &RadarBlip, Mut<Style>)>,
+    mut viewports: Query<With<RadarViewport, Mut<Style>>>,
+) {
+    let radar = match radars.iter().next() {
+        Some(radar) => radar,
+        None => return,
+    };
+    let center = match (arena.edge, players.iter().next()) {
+        (ArenaEdge::Wrap, Some((_, transform))) => transform.translation.truncate(),
+        _ => Vec2::zero(),
+    };
+    let mut targets = HashMap::new();
+    for (entity, transform) in players.iter() {
+        targets.insert(entity, (transform.translation.truncate(), BlipKind::Player));
+    }
+    for (entity, transform, wave_member) in enemies.iter() {
+        let kind = match wave_member {
+            Some(_) => BlipKind::WaveEnemy,
+            None => BlipKind::Asteroid,
+        };
+        targets.insert(entity, (transform.translation.truncate(), kind));
+    }
+    for (entity, transform) in loots.iter() {
+        targets.insert(entity, (transform.translation.truncate(), BlipKind::Loot));
+    }
+    for (entity, transform) in wells.iter() {
+        targets.insert(
+            entity,
+            (transform.translation.truncate(), BlipKind::GravityWell),
+        );
+    }
+    for (blip_entity, blip, mut style) in blips.iter_mut() {
+        match targets.remove(&blip.target) {
+            Some((position, _)) => {
+                let position = radar_position(&arena, center, position);
+                set_position(&mut style, position - Vec2::splat(BLIP_SIZE / 2.0));
+            }
+            None => {
+                commands.despawn_recursive(blip_entity);
+            }
+        }
+    }
+    // Remaining targets have no blip yet.
+    for (target, (position, kind)) in targets {
+        let mut node = absolute_node(radar_materials.blips[&kind].clone(), Vec2::splat(BLIP_SIZE));
+        let position = radar_position(&arena, center, position);
+        set_position(&mut node.style, position - Vec2::splat(BLIP_SIZE / 2.0));
+        commands.spawn(node).with(RadarBlip { target });
+        let blip = commands.current_entity().unwrap();
+        commands.push_children(radar, &[blip]);
+    }
+    let radar_scale = RADAR_WIDTH / arena.size.x();
+    let radar_size = Vec2::new(RADAR_WIDTH, RADAR_WIDTH * arena.size.y() / arena.size.x());
+    for mut style in viewports.iter_mut() {
+        let size = (arena.view_size * radar_scale).min(radar_size);
+        let position = radar_position(&arena, center, arena.view_center) - size / 2.0;
+        // Keep the rectangle inside the radar, when not wrapping the view can go past the edges.
+        let position = position.max(Vec2::zero()).min(radar_size - size);
+        set_position(&mut style, position);
+        style.size = Size::new(Val::Px(size.x()), Val::Px(size.y()));
+    }
+}
+
+/// Show markers on the window edges, pointing the shortest way toward off-screen enemies.
+pub fn offscreen_indicator_system(
+    mut commands: Commands,
+    arena: Res<Arena>,
+    windows: Res<Windows>,
+    radar_materials: Res<RadarMaterials>,
+    enemies: Query<With<Enemy, (Entity, &Transform, Option<&WaveMember>)>>,
+    mut indicators: Query<(Entity, &OffscreenIndicator, Mut<Style>)>,
+) {
+    let window = match windows.get_primary() {
+        Some(window) => window,
+        None => return,
+    };
+    let window_size = Vec2::new(window.width() as f32, window.height() as f32);
+    if arena.view_size.x() <= 0.0 {
+        return;
+    }
+    // World units per pixel
+    let zoom = arena.view_size.x() / window_size.x();
+    let half_window = window_size / 2.0;
+    let half_edges = half_window - Vec2::splat(INDICATOR_MARGIN);
+    let mut offscreen = HashMap::new();
+    for (entity, transform, wave_member) in enemies.iter() {
+        let delta = arena.wrapped_delta(arena.view_center, transform.translation.truncate()) / zoom;
+        if delta.x().abs() <= half_window.x() && delta.y().abs() <= half_window.y() {
+            continue;
+        }
+        // Scale the direction down until it touches the window edges.
+        let scale = (half_edges.x() / delta.x().abs()).min(half_edges.y() / delta.y().abs());
+        let position = half_window + delta * scale - Vec2::splat(INDICATOR_SIZE / 2.0);
+        let kind = match wave_member {
+            Some(_) => BlipKind::WaveEnemy,
+            None => BlipKind::Asteroid,
+        };
+        offscreen.insert(entity, (position, kind));
+    }
+    for (indicator_entity, indicator, mut style) in indicators.iter_mut() {
+        match offscreen.remove(&indicator.target) {
+            Some((position, _)) => set_position(&mut style, position),
+            None => {
+                commands.despawn_recursive(indicator_entity);
+            }
+        }
+    }
+    for (target, (position, kind)) in offscreen {
+        let mut node = absolute_node(
+            radar_materials.blips[&kind].clone(),
+            Vec2::splat(INDICATOR_SIZE),
+        );
+        set_position(&mut node.style, position);
+        commands.spawn(node).with(OffscreenIndicator { target });
+    }
+}
@@ -0,0 +1,1011 @@
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, VecDeque},
+    hash::{Hash, Hasher},
+    net::{SocketAddr, UdpSocket},
+};
+
+use super::*;
+use ncollide2d::pipeline::{CollisionGroups, CollisionObjectSlabHandle};
+use serde::{Deserialize, Serialize};
+
+/// Simulation steps per second, the same on both peers.
+const VERSUS_TICK_RATE: f32 = 60.0;
+const VERSUS_DT: f32 = 1.0 / VERSUS_TICK_RATE;
+/// Local inputs are applied this many frames later, hiding most of the latency.
+const INPUT_DELAY: u32 = 2;
+/// Frames simulated past the last confirmed remote input, before waiting for the peer.
+const MAX_ROLLBACK: u32 = 8;
+/// Steps run in a single update, when catching up after a slow frame.
+const MAX_STEPS_PER_UPDATE: u32 = 4;
+/// Checksums kept to be compared with the peer ones.
+const CHECKSUM_HISTORY: usize = 64;
+const VERSUS_HELLO_INTERVAL: f32 = 0.25;
+
+const SHIP_LIFE: u32 = 3;
+const SHIP_RADIUS: f32 = 15.0;
+const SHIP_THRUST: f32 = 900.0;
+/// Speed factor kept after 1s.
+const SHIP_DAMPENING: f32 = 0.1;
+const SHIP_TURN_RATE: f32 = 2.0 * PI;
+const FIRE_COOLDOWN: u32 = 20;
+const MISSILE_RADIUS: f32 = 5.0;
+const MISSILE_SPEED: f32 = 700.0;
+const MISSILE_LIFETIME: u32 = 90;
+/// Frames between a ship destruction and the next round.
+const ROUND_RESTART: u32 = 120;
+
+/// Buttons and heading of a player for one frame, compact and exactly comparable.
+#[derive(Serialize, Deserialize, Default, Copy, Clone, Eq, PartialEq, Debug)]
+pub struct VersusInput {
+    pub buttons: u8,
+    /// Heading, in 1/65536 of a turn.
+    pub aim: u16,
+}
+
+impl VersusInput {
+    pub const FORWARD: u8 = 1;
+    pub const BACKWARD: u8 = 2;
+    pub const LEFT: u8 = 4;
+    pub const RIGHT: u8 = 8;
+    pub const FIRE: u8 = 16;
+    pub fn pressed(&self, button: u8) -> bool {
+        self.buttons & button != 0
+    }
+    fn axis(&self, positive: u8, negative: u8) -> f32 {
+        self.pressed(positive) as i32 as f32 - self.pressed(negative) as i32 as f32
+    }
+    pub fn aim_angle(&self) -> f32 {
+        normalize_angle(self.aim as f32 / 65536.0 * 2.0 * PI)
+    }
+    /// Quantize the controls, keeping the previous heading when there is no aim.
+    pub fn from_frame(frame: InputFrame, previous: VersusInput) -> VersusInput {
+        let mut buttons = 0;
+        for &(pressed, button) in [
+            (frame.thrust > 0.5, VersusInput::FORWARD),
+            (frame.thrust < -0.5, VersusInput::BACKWARD),
+            (frame.strafe > 0.5, VersusInput::LEFT),
+            (frame.strafe < -0.5, VersusInput::RIGHT),
+            (frame.fire, VersusInput::FIRE),
+        ]
+        .iter()
+        {
+            if pressed {
+                buttons |= button;
+            }
+        }
+        let aim = frame.aim.map_or(previous.aim, |angle| {
+            ((angle / (2.0 * PI)).rem_euclid(1.0) * 65536.0) as u32 as u16
+        });
+        VersusInput { buttons, aim }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct VersusShip {
+    pub position: Vec2,
+    pub speed: Vec2,
+    pub angle: f32,
+    pub life: u32,
+    /// Frames before the next shot.
+    pub cooldown: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct VersusMissile {
+    pub id: u32,
+    pub owner: usize,
+    pub position: Vec2,
+    pub speed: Vec2,
+    pub lifetime: u32,
+}
+
+/// Whole simulation of a versus match, cloned to be restored on rollback.
+#[derive(Clone)]
+pub struct VersusState {
+    /// Next frame to simulate.
+    pub frame: u32,
+    pub arena_size: Vec2,
+    pub ships: [VersusShip; 2],
+    pub missiles: Vec<VersusMissile>,
+    next_missile: u32,
+    pub scores: [u32; 2],
+    /// Frames until the next round, after a ship is destroyed.
+    pub round_restart: Option<u32>,
+    rng: StdRng,
+}
+
+/// Bring the position back inside the torus arena.
+fn wrap(position: Vec2, size: Vec2) -> Vec2 {
+    let wrap = |value: f32, size: f32| (value + size / 2.0).rem_euclid(size) - size / 2.0;
+    Vec2::new(wrap(position.x(), size.x()), wrap(position.y(), size.y()))
+}
+
+impl VersusState {
+    fn new(seed: u64, arena_size: Vec2) -> VersusState {
+        let ship = VersusShip {
+            position: Vec2::zero(),
+            speed: Vec2::zero(),
+            angle: 0.0,
+            life: SHIP_LIFE,
+            cooldown: 0,
+        };
+        let mut state = VersusState {
+            frame: 0,
+            arena_size,
+            ships: [ship.clone(), ship],
+            missiles: Vec::new(),
+            next_missile: 0,
+            scores: [0, 0],
+            round_restart: None,
+            rng: StdRng::seed_from_u64(seed),
+        };
+        state.start_round();
+        state
+    }
+    /// Put the ships back on their own half of the arena, facing each other.
+    fn start_round(&mut self) {
+        for side in 0..2 {
+            let direction = if side == 0 { -1.0 } else { 1.0 };
+            let x = self.rng.gen_range(0.15, 0.35) * self.arena_size.x() * direction;
+            let y = self.rng.gen_range(-0.25, 0.25) * self.arena_size.y();
+            self.ships[side] = VersusShip {
+                position: Vec2::new(x, y),
+                speed: Vec2::zero(),
+                angle: if side == 0 { 0.0 } else { PI },
+                life: SHIP_LIFE,
+                cooldown: 0,
+            };
+        }
+        self.missiles.clear();
+        self.round_restart = None;
+    }
+    /// Move the ships and missiles by one frame, hits are applied afterwards.
+    fn advance(&mut self, inputs: [VersusInput; 2]) {
+        match self.round_restart {
+            Some(0) => self.start_round(),
+            Some(frames) => self.round_restart = Some(frames - 1),
+            None => {}
+        }
+        let dampening = SHIP_DAMPENING.powf(VERSUS_DT);
+        let max_turn = SHIP_TURN_RATE * VERSUS_DT;
+        let size = self.arena_size;
+        for side in 0..2 {
+            // Controls are locked between two rounds.
+            let input = match self.round_restart {
+                Some(_) => VersusInput::default(),
+                None => inputs[side],
+            };
+            let ship = &mut self.ships[side];
+            if ship.life == 0 {
+                continue;
+            }
+            let delta_angle = normalize_angle(input.aim_angle() - ship.angle);
+            ship.angle = normalize_angle(ship.angle + delta_angle.max(-max_turn).min(max_turn));
+            let forward = Vec2::new(ship.angle.cos(), ship.angle.sin());
+            let left = Vec2::new(-forward.y(), forward.x());
+            let thrust = forward * input.axis(VersusInput::FORWARD, VersusInput::BACKWARD)
+                + left * input.axis(VersusInput::LEFT, VersusInput::RIGHT) * 0.5;
+            ship.speed = (ship.speed + thrust * SHIP_THRUST * VERSUS_DT) * dampening;
+            ship.position = wrap(ship.position + ship.speed * VERSUS_DT, size);
+            ship.cooldown = ship.cooldown.saturating_sub(1);
+            if input.pressed(VersusInput::FIRE) && ship.cooldown == 0 {
+                ship.cooldown = FIRE_COOLDOWN;
+                self.next_missile += 1;
+                self.missiles.push(VersusMissile {
+                    id: self.next_missile,
+                    owner: side,
+                    position: ship.position,
+                    speed: ship.speed + forward * MISSILE_SPEED,
+                    lifetime: MISSILE_LIFETIME,
+                });
+            }
+        }
+        for missile in self.missiles.iter_mut() {
+            missile.position = wrap(missile.position + missile.speed * VERSUS_DT, size);
+            missile.lifetime -= 1;
+        }
+        self.missiles.retain(|missile| missile.lifetime > 0);
+    }
+    /// Hits are (missile id, ship side), sorted so both peers apply them in the same order.
+    fn apply_hits(&mut self, hits: &[(u32, usize)]) {
+        for &(id, side) in hits {
+            // A missile touching both ships only hits the first.
+            let index = match self.missiles.iter().position(|missile| missile.id == id) {
+                Some(index) => index,
+                None => continue,
+            };
+            self.missiles.remove(index);
+            let ship = &mut self.ships[side];
+            if ship.life == 0 {
+                continue;
+            }
+            ship.life -= 1;
+            if ship.life == 0 && self.round_restart.is_none() {
+                self.scores[1 - side] += 1;
+                self.round_restart = Some(ROUND_RESTART);
+            }
+        }
+    }
+    /// Simulate one frame: move, then apply the hits found by the collision world.
+    fn step(&mut self, inputs: [VersusInput; 2], collisions: &mut VersusCollisions) {
+        self.advance(inputs);
+        let hits = collisions.hits(self);
+        self.apply_hits(&hits);
+        self.frame += 1;
+    }
+    /// Hash of everything simulated, compared between peers to detect desyncs.
+    pub fn checksum(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        let hash_vec2 = |hasher: &mut DefaultHasher, value: Vec2| {
+            value.x().to_bits().hash(hasher);
+            value.y().to_bits().hash(hasher);
+        };
+        self.frame.hash(&mut hasher);
+        for ship in self.ships.iter() {
+            hash_vec2(&mut hasher, ship.position);
+            hash_vec2(&mut hasher, ship.speed);
+            ship.angle.to_bits().hash(&mut hasher);
+            ship.life.hash(&mut hasher);
+            ship.cooldown.hash(&mut hasher);
+        }
+        for missile in self.missiles.iter() {
+            missile.id.hash(&mut hasher);
+            missile.owner.hash(&mut hasher);
+            hash_vec2(&mut hasher, missile.position);
+            hash_vec2(&mut hasher, missile.speed);
+            missile.lifetime.hash(&mut hasher);
+        }
+        self.next_missile.hash(&mut hasher);
+        self.scores.hash(&mut hasher);
+        self.round_restart.hash(&mut hasher);
+        // The generator state is not visible, its next draw stands for it.
+        self.rng.clone().next_u64().hash(&mut hasher);
+        hasher.finish()
+    }
+}
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum VersusBody {
+    Ship(usize),
+    Missile(u32),
+}
+
+/// Collision world of the match. It mirrors the bodies of the state before each step,
+/// so restoring a state also restores the colliders.
+struct VersusCollisions {
+    world: CollisionWorld<f32, VersusBody>,
+    bodies: HashMap<VersusBody, CollisionObjectSlabHandle>,
+    ships: CollisionGroups,
+    missiles: CollisionGroups,
+}
+
+impl VersusCollisions {
+    fn new() -> VersusCollisions {
+        VersusCollisions {
+            world: CollisionWorld::new(0.02),
+            bodies: HashMap::new(),
+            ships: CollisionGroups::new()
+                .with_membership(&[1])
+                .with_whitelist(&[2]),
+            missiles: CollisionGroups::new()
+                .with_membership(&[2])
+                .with_whitelist(&[1]),
+        }
+    }
+    /// Missiles touching a ship other than their owner.
+    fn hits(&mut self, state: &VersusState) -> Vec<(u32, usize)> {
+        let mut wanted = HashMap::new();
+        for (side, ship) in state.ships.iter().enumerate() {
+            if ship.life > 0 {
+                wanted.insert(VersusBody::Ship(side), ship.position);
+            }
+        }
+        for missile in state.missiles.iter() {
+            wanted.insert(VersusBody::Missile(missile.id), missile.position);
+        }
+        let stale: Vec<VersusBody> = self
+            .bodies
+            .keys()
+            .filter(|body| !wanted.contains_key(*body))
+            .copied()
+            .collect();
+        for body in stale {
+            let handle = self.bodies.remove(&body).unwrap();
+            self.world.remove(&[handle]);
+        }
+        for (body, position) in wanted {
+            let isometry = Isometry2::new(Vector2::new(position.x(), position.y()), na::zero());
+            match self.bodies.get(&body) {
+                Some(&handle) => self.world.get_mut(handle).unwrap().set_position(isometry),
+                None => {
+                    let (radius, groups) = match body {
+                        VersusBody::Ship(_) => (SHIP_RADIUS, self.ships),
+                        VersusBody::Missile(_) => (MISSILE_RADIUS, self.missiles),
+                    };
+                    let (handle, _) = self.world.add(
+                        isometry,
+                        ShapeHandle::new(Ball::new(radius)),
+                        groups,
+                        GeometricQueryType::Contacts(0.0, 0.0),
+                        body,
+                    );
+                    self.bodies.insert(body, handle);
+                }
+            }
+        }
+        self.world.update();
+        let mut hits = Vec::new();
+        for (h1, h2, _, manifold) in self.world.contact_pairs(true) {
+            if manifold.deepest_contact().is_none() {
+                continue;
+            }
+            let b1 = *self.world.collision_object(h1).unwrap().data();
+            let b2 = *self.world.collision_object(h2).unwrap().data();
+            let (id, side) = match (b1, b2) {
+                (VersusBody::Missile(id), VersusBody::Ship(side))
+                | (VersusBody::Ship(side), VersusBody::Missile(id)) => (id, side),
+                _ => continue,
+            };
+            let own_missile = state
+                .missiles
+                .iter()
+                .any(|missile| missile.id == id && missile.owner == side);
+            if !own_missile {
+                hits.push((id, side));
+            }
+        }
+        // Contact pairs come in no particular order.
+        hits.sort();
+        hits
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+enum VersusMessage {
+    Hello {
+        nonce: u64,
+        arena_size: (f32, f32),
+    },
+    /// Local inputs from frame start on, not yet acknowledged by the peer.
+    Inputs {
+        start: u32,
+        inputs: Vec<VersusInput>,
+        /// Frames before this one have all their inputs received.
+        ack: u32,
+        /// Checksum of the latest frame simulated with confirmed inputs only.
+        checksum: Option<(u32, u64)>,
+    },
+}
+
+/// Rollback netcode of a 1v1 match: remote inputs are predicted, and the frames are
+/// simulated again from a saved state when the prediction was wrong.
+pub struct RollbackSession {
+    socket: UdpSocket,
+    peer: SocketAddr,
+    nonce: u64,
+    arena_size: Vec2,
+    hello_timer: Timer,
+    peer_started: bool,
+    /// Side of the local player, 0 or 1, known once the peer answered.
+    pub local_side: Option<usize>,
+    pub state: Option<VersusState>,
+    collisions: VersusCollisions,
+    /// States before simulating each frame, back to the first unconfirmed one.
+    saved: BTreeMap<u32, VersusState>,
+    local_inputs: BTreeMap<u32, VersusInput>,
+    remote_inputs: BTreeMap<u32, VersusInput>,
+    /// Remote inputs guessed for the frames simulated without them.
+    predicted: BTreeMap<u32, VersusInput>,
+    /// Frames before this one have their remote inputs.
+    confirmed: u32,
+    /// Frames before this one have their local inputs received by the peer.
+    remote_ack: u32,
+    checksums: BTreeMap<u32, u64>,
+    remote_checksums: BTreeMap<u32, u64>,
+    /// First frame whose checksums differ from the peer ones.
+    pub desync: Option<u32>,
+    accumulator: f32,
+    /// Input of the local player for the next frame.
+    pub pending_input: VersusInput,
+    /// Outgoing packets held back to fake latency, with the update they are sent at.
+    outgoing: VecDeque<(u32, VersusMessage)>,
+    send_delay: u32,
+    updates: u32,
+    pub rollbacks: u32,
+    pub resimulated: u32,
+}
+
+impl RollbackSession {
+    pub fn new(socket: UdpSocket, peer: SocketAddr, arena_size: Vec2, send_delay: u32) -> Self {
+        RollbackSession {
+            socket,
+            peer,
+            nonce: thread_rng().gen(),
+            arena_size,
+            hello_timer: Timer::from_seconds(VERSUS_HELLO_INTERVAL, true),
+            peer_started: false,
+            local_side: None,
+            state: None,
+            collisions: VersusCollisions::new(),
+            saved: BTreeMap::new(),
+            local_inputs: BTreeMap::new(),
+            remote_inputs: BTreeMap::new(),
+            predicted: BTreeMap::new(),
+            // Nobody plays during the input delay of the first frames.
+            confirmed: INPUT_DELAY,
+            remote_ack: INPUT_DELAY,
+            checksums: BTreeMap::new(),
+            remote_checksums: BTreeMap::new(),
+            desync: None,
+            accumulator: 0.0,
+            pending_input: VersusInput::default(),
+            outgoing: VecDeque::new(),
+            send_delay,
+            updates: 0,
+            rollbacks: 0,
+            resimulated: 0,
+        }
+    }
+    pub fn local_ship(&self) -> Option<&VersusShip> {
+        Some(&self.state.as_ref()?.ships[self.local_side?])
+    }
+    /// Exchange inputs with the peer, roll back on mispredictions and advance the fixed steps.
+    pub fn update(&mut self, delta_seconds: f32) {
+        self.updates += 1;
+        if !self.peer_started {
+            self.hello_timer.tick(delta_seconds);
+            if self.hello_timer.just_finished {
+                let hello = VersusMessage::Hello {
+                    nonce: self.nonce,
+                    arena_size: (self.arena_size.x(), self.arena_size.y()),
+                };
+                self.send(hello);
+            }
+        }
+        let mispredicted = self.receive();
+        if self.state.is_none() {
+            self.flush();
+            return;
+        }
+        if let Some(frame) = mispredicted {
+            self.rollback(frame);
+        }
+        self.accumulator += delta_seconds;
+        let mut steps = 0;
+        while self.accumulator >= VERSUS_DT && steps < MAX_STEPS_PER_UPDATE {
+            let frame = self.state.as_ref().unwrap().frame;
+            if frame >= self.confirmed + MAX_ROLLBACK {
+                // Too far ahead of the peer, wait for its inputs.
+                self.accumulator = self.accumulator.min(VERSUS_DT);
+                break;
+            }
+            self.accumulator -= VERSUS_DT;
+            steps += 1;
+            self.local_inputs
+                .insert(frame + INPUT_DELAY, self.pending_input);
+            self.simulate_frame();
+        }
+        self.accumulator = self
+            .accumulator
+            .min(VERSUS_DT * MAX_STEPS_PER_UPDATE as f32);
+        self.record_checksum();
+        self.send_inputs();
+        self.prune();
+        self.flush();
+    }
+    /// Handle the peer packets, return the first frame simulated with a wrong guess.
+    fn receive(&mut self) -> Option<u32> {
+        let mut mispredicted: Option<u32> = None;
+        for (address, message) in receive_packets::<VersusMessage>(&self.socket) {
+            if address != self.peer {
+                continue;
+            }
+            match message {
+                VersusMessage::Hello { nonce, arena_size } => {
+                    if self.state.is_some() {
+                        continue;
+                    }
+                    if nonce == self.nonce {
+                        println!("Versus peer picked the same nonce, waiting for a new one");
+                        self.nonce = thread_rng().gen();
+                        continue;
+                    }
+                    // The lowest nonce plays the first side, and sets the seed and the arena.
+                    let side = if self.nonce < nonce { 0 } else { 1 };
+                    let arena_size = if side == 0 {
+                        self.arena_size
+                    } else {
+                        Vec2::new(arena_size.0, arena_size.1)
+                    };
+                    println!("Versus match against {}, playing side {}", self.peer, side);
+                    self.local_side = Some(side);
+                    self.state = Some(VersusState::new(self.nonce.min(nonce), arena_size));
+                }
+                VersusMessage::Inputs {
+                    start,
+                    inputs,
+                    ack,
+                    checksum,
+                } => {
+                    self.peer_started = true;
+                    self.remote_ack = self.remote_ack.max(ack);
+                    for (frame, input) in (start..).zip(inputs) {
+                        if frame < self.confirmed || self.remote_inputs.contains_key(&frame) {
+                            continue;
+                        }
+                        self.remote_inputs.insert(frame, input);
+                        if let Some(guess) = self.predicted.remove(&frame) {
+                            if guess != input {
+                                mispredicted = Some(mispredicted.map_or(frame, |f| f.min(frame)));
+                            }
+                        }
+                    }
+                    while self.remote_inputs.contains_key(&self.confirmed) {
+                        self.confirmed += 1;
+                    }
+                    if let Some((frame, checksum)) = checksum {
+                        self.remote_checksums.insert(frame, checksum);
+                    }
+                }
+            }
+        }
+        mispredicted
+    }
+    /// Inputs of both sides for the frame, guessing the remote one if not received yet.
+    fn inputs_for(&mut self, frame: u32) -> [VersusInput; 2] {
+        let local = self.local_inputs.get(&frame).copied().unwrap_or_default();
+        let remote = match self.remote_inputs.get(&frame) {
+            Some(&input) => input,
+            None => {
+                // Players mostly hold their controls, repeat the last known input.
+                let guess = self
+                    .remote_inputs
+                    .range(..frame)
+                    .next_back()
+                    .map_or(VersusInput::default(), |(_, &input)| input);
+                if frame >= INPUT_DELAY {
+                    self.predicted.insert(frame, guess);
+                }
+                guess
+            }
+        };
+        match self.local_side {
+            Some(0) => [local, remote],
+            _ => [remote, local],
+        }
+    }
+    fn simulate_frame(&mut self) {
+        let frame = self.state.as_ref().unwrap().frame;
+        let inputs = self.inputs_for(frame);
+        let state = self.state.as_mut().unwrap();
+        self.saved.insert(frame, state.clone());
+        state.step(inputs, &mut self.collisions);
+    }
+    /// Restore the state before the frame and simulate again up to the current one.
+    fn rollback(&mut self, frame: u32) {
+        let saved = match self.saved.get(&frame) {
+            Some(saved) => saved.clone(),
+            None => {
+                println!("No state saved for frame {}, can't roll back", frame);
+                return;
+            }
+        };
+        let state = self.state.as_mut().unwrap();
+        let current = state.frame;
+        *state = saved;
+        self.rollbacks += 1;
+        while self.state.as_ref().unwrap().frame < current {
+            self.simulate_frame();
+            self.resimulated += 1;
+        }
+    }
+    /// Checksum the state reached with confirmed inputs only, and compare it with the peer one.
+    fn record_checksum(&mut self) {
+        let state = self.state.as_ref().unwrap();
+        let checksum = if state.frame == self.confirmed {
+            state.checksum()
+        } else if let Some(saved) = self.saved.get(&self.confirmed) {
+            saved.checksum()
+        } else {
+            return;
+        };
+        self.checksums.insert(self.confirmed, checksum);
+        for (frame, remote) in self.remote_checksums.iter() {
+            match self.checksums.get(frame) {
+                Some(local) if local != remote && self.desync.is_none() => {
+                    self.desync = Some(*frame);
+                }
+                _ => {}
+            }
+        }
+        let checksums = &self.checksums;
+        self.remote_checksums
+            .retain(|frame, _| !checksums.contains_key(frame));
+        while self.checksums.len() > CHECKSUM_HISTORY {
+            let oldest = *self.checksums.keys().next().unwrap();
+            self.checksums.remove(&oldest);
+        }
+        while self.remote_checksums.len() > CHECKSUM_HISTORY {
+            let oldest = *self.remote_checksums.keys().next().unwrap();
+            self.remote_checksums.remove(&oldest);
+        }
+    }
+    fn send_inputs(&mut self) {
+        let message = VersusMessage::Inputs {
+            start: self.remote_ack,
+            inputs: self
+                .local_inputs
+                .range(self.remote_ack..)
+                .map(|(_, &input)| input)
+                .collect(),
+            ack: self.confirmed,
+            checksum: self
+                .checksums
+                .iter()
+                .next_back()
+                .map(|(&frame, &checksum)| (frame, checksum)),
+        };
+        self.send(message);
+    }
+    /// Forget what can't be rolled back to anymore.
+    fn prune(&mut self) {
+        let confirmed = self.confirmed;
+        let keep_local = confirmed.min(self.remote_ack);
+        self.saved = self.saved.split_off(&confirmed);
+        self.local_inputs = self.local_inputs.split_off(&keep_local);
+        self.predicted = self.predicted.split_off(&confirmed);
+        // The last confirmed remote input is the guess for the next frames.
+        let keep_remote = confirmed.saturating_sub(1);
+        self.remote_inputs = self.remote_inputs.split_off(&keep_remote);
+    }
+    fn send(&mut self, message: VersusMessage) {
+        self.outgoing
+            .push_back((self.updates + self.send_delay, message));
+    }
+    fn flush(&mut self) {
+        while let Some((update, _)) = self.outgoing.front() {
+            if *update > self.updates {
+                break;
+            }
+            let (_, message) = self.outgoing.pop_front().unwrap();
+            send_packet(&self.socket, self.peer, &message);
+        }
+    }
+}
+
+/// Input of the loopback opponent: turn toward the player, shoot, and dodge now and then.
+fn loopback_bot_input(session: &RollbackSession) -> VersusInput {
+    let (state, side) = match (&session.state, session.local_side) {
+        (Some(state), Some(side)) => (state, side),
+        _ => return VersusInput::default(),
+    };
+    let ship = &state.ships[side];
+    let target = &state.ships[1 - side];
+    let delta = target.position - ship.position;
+    let frame = InputFrame {
+        thrust: if (state.frame / 90) % 2 == 0 {
+            1.0
+        } else {
+            0.0
+        },
+        strafe: if (state.frame / 45) % 3 == 0 {
+            1.0
+        } else {
+            0.0
+        },
+        aim: Some(delta.y().atan2(delta.x())),
+        fire: true,
+    };
+    VersusInput::from_frame(frame, session.pending_input)
+}
+
+pub fn versus_system(time: Res<Time>, mut network: ResMut<Network>) {
+    let dt = time.delta_seconds;
+    if let Some(bot) = network.versus_bot.as_mut() {
+        bot.pending_input = loopback_bot_input(bot);
+        bot.update(dt);
+    }
+    let session = match network.versus.as_mut() {
+        Some(session) => session,
+        None => return,
+    };
+    session.update(dt);
+}
+
+/// Tag component for the text showing the versus score and whether the peers are in sync.
+pub struct VersusText;
+
+pub fn setup_versus_ui(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    network: Res<Network>,
+) {
+    if network.versus.is_none() {
+        return;
+    }
+    commands
+        .spawn(TextComponents {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Px(10.0),
+                    top: Val::Px(10.0),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            text: Text {
+                value: "".to_string(),
+                font: asset_server.load("FiraSans-Bold.ttf"),
+                style: TextStyle {
+                    font_size: 24.0,
+                    color: Color::rgb(0.8, 0.8, 0.9),
+                },
+            },
+            ..Default::default()
+        })
+        .with(VersusText);
+}
+
+pub fn versus_ui_system(network: Res<Network>, mut texts: Query<With<VersusText, Mut<Text>>>) {
+    let session = match network.versus.as_ref() {
+        Some(session) => session,
+        None => return,
+    };
+    let value = match (&session.state, session.local_side) {
+        (Some(state), Some(side)) => {
+            let sync = match session.desync {
+                Some(frame) => format!("Desync at frame {}", frame),
+                None => format!(
+                    "In sync, {} rollbacks, {} frames simulated again",
+                    session.rollbacks, session.resimulated
+                ),
+            };
+            format!(
+                "You {} - {} Opponent  {}",
+                state.scores[side],
+                state.scores[1 - side],
+                sync
+            )
+        }
+        _ => "Waiting for the opponent".to_string(),
+    };
+    for mut text in texts.iter_mut() {
+        if text.value != value {
+            text.value = value.clone();
+        }
+    }
+}
+
+/// Show the predicted state of the match, the local ship is followed by the camera.
+pub fn versus_render_system(
+    mut commands: Commands,
+    (network, mut arena): (Res<Network>, ResMut<Arena>),
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    mut sprites: Local<HashMap<VersusBody, Entity>>,
+    cameras: Query<With<CameraRig, Entity>>,
+    mut transforms: Query<Mut<Transform>>,
+) {
+    let session = match network.versus.as_ref() {
+        Some(session) => session,
+        None => return,
+    };
+    let (state, local_side) = match (&session.state, session.local_side) {
+        (Some(state), Some(side)) => (state, side),
+        _ => return,
+    };
+    if arena.size != state.arena_size || arena.edge != ArenaEdge::Wrap {
+        *arena = Arena::new(state.arena_size, ArenaEdge::Wrap);
+    }
+    let mut bodies = Vec::new();
+    for (side, ship) in state.ships.iter().enumerate() {
+        if ship.life > 0 {
+            bodies.push((VersusBody::Ship(side), ship.position, ship.angle));
+        }
+    }
+    for missile in state.missiles.iter() {
+        let angle = missile.speed.y().atan2(missile.speed.x());
+        bodies.push((VersusBody::Missile(missile.id), missile.position, angle));
+    }
+    let camera = cameras.iter().next();
+    let mut alive = Vec::new();
+    for (body, position, angle) in bodies {
+        alive.push(body);
+        let (asset, z, scale, color) = match body {
+            VersusBody::Ship(side) if side == local_side => {
+                ("playerShip1_red.png", 0.0, 0.3, Color::WHITE)
+            }
+            VersusBody::Ship(_) => ("playerShip1_red.png", 0.0, 0.3, Color::rgb(1.0, 0.5, 0.5)),
+            VersusBody::Missile(_) => ("laserRed07.png", -0.1, 0.6, Color::WHITE),
+        };
+        let transform = Transform {
+            translation: Vec3::new(position.x(), position.y(), z),
+            rotation: Quat::from_rotation_z(angle),
+            scale: Vec3::splat(scale),
+        };
+        if let Some(&entity) = sprites.get(&body) {
+            if let Ok(mut current) = transforms.get_component_mut::<Transform>(entity) {
+                *current = transform;
+            }
+            continue;
+        }
+        commands.spawn_with_ghosts(SpriteComponents {
+            material: materials.add(ColorMaterial::modulated_texture(
+                asset_server.load(asset),
+                color,
+            )),
+            transform,
+            ..Default::default()
+        });
+        let entity = commands.current_entity().unwrap();
+        if body == VersusBody::Ship(local_side) {
+            if let Some(camera) = camera {
+                commands.insert_one(entity, FollowedCamera(camera));
+            }
+        }
+        sprites.insert(body, entity);
+    }
+    let gone: Vec<VersusBody> = sprites
+        .keys()
+        .filter(|body| !alive.contains(*body))
+        .copied()
+        .collect();
+    for body in gone {
+        commands.despawn_from_arena(sprites.remove(&body).unwrap());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    const SEED: u64 = 42;
+
+    fn test_arena_size() -> Vec2 {
+        Vec2::new(1000.0, 800.0)
+    }
+
+    /// Stand still, aim at the opponent and fire in bursts, so inputs change but missiles hit.
+    fn scripted_input(state: &VersusState, side: usize) -> VersusInput {
+        let delta = state.ships[1 - side].position - state.ships[side].position;
+        let frame = InputFrame {
+            aim: Some(delta.y().atan2(delta.x())),
+            fire: (state.frame / 7 + side as u32) % 2 == 0,
+            ..Default::default()
+        };
+        VersusInput::from_frame(frame, VersusInput::default())
+    }
+
+    #[test]
+    fn late_inputs_roll_back_to_the_same_state() {
+        const FRAMES: usize = 400;
+        const LATENCY: usize = 5;
+        // Straight run, with every input known in time.
+        let mut reference = VersusState::new(SEED, test_arena_size());
+        let mut collisions = VersusCollisions::new();
+        let mut inputs = Vec::new();
+        for _ in 0..FRAMES {
+            let frame_inputs = [scripted_input(&reference, 0), scripted_input(&reference, 1)];
+            reference.step(frame_inputs, &mut collisions);
+            inputs.push(frame_inputs);
+        }
+        assert!(
+            reference.ships.iter().any(|ship| ship.life < SHIP_LIFE) || reference.scores != [0, 0]
+        );
+
+        // Same inputs, those of side 1 arriving LATENCY frames late and predicted meanwhile.
+        let mut state = VersusState::new(SEED, test_arena_size());
+        let mut collisions = VersusCollisions::new();
+        let mut saved: Vec<VersusState> = Vec::new();
+        let mut used: Vec<VersusInput> = Vec::new();
+        let mut rollbacks = 0;
+        for time in 0..=FRAMES + LATENCY {
+            // Remote inputs of the frames before this one have arrived.
+            let known = time.saturating_sub(LATENCY).min(FRAMES);
+            // Missing ones are predicted as the last arrived input.
+            let remote = |frame: usize| {
+                if frame < known {
+                    inputs[frame][1]
+                } else if known > 0 {
+                    inputs[known - 1][1]
+                } else {
+                    VersusInput::default()
+                }
+            };
+            let simulated = used.len();
+            if let Some(wrong) =
+                (0..known.min(simulated)).find(|&frame| used[frame] != inputs[frame][1])
+            {
+                state = saved[wrong].clone();
+                saved.truncate(wrong);
+                used.truncate(wrong);
+                rollbacks += 1;
+            }
+            while used.len() < simulated.max((time + 1).min(FRAMES)) {
+                let frame = used.len();
+                saved.push(state.clone());
+                used.push(remote(frame));
+                state.step([inputs[frame][0], remote(frame)], &mut collisions);
+            }
+        }
+        assert!(rollbacks > 0);
+        assert_eq!(state.frame, reference.frame);
+        assert_eq!(state.checksum(), reference.checksum());
+    }
+
+    #[test]
+    fn inputs_are_quantized() {
+        let frame = |thrust: f32, strafe: f32, aim: Option<f32>, fire: bool| InputFrame {
+            thrust,
+            strafe,
+            aim,
+            fire,
+        };
+        let none = VersusInput::default();
+        let buttons = |input: InputFrame| VersusInput::from_frame(input, none).buttons;
+        assert_eq!(buttons(frame(1.0, 0.0, None, false)), VersusInput::FORWARD);
+        assert_eq!(
+            buttons(frame(-1.0, 0.0, None, false)),
+            VersusInput::BACKWARD
+        );
+        assert_eq!(buttons(frame(0.0, 1.0, None, false)), VersusInput::LEFT);
+        assert_eq!(buttons(frame(0.0, -1.0, None, false)), VersusInput::RIGHT);
+        assert_eq!(buttons(frame(0.0, 0.0, None, true)), VersusInput::FIRE);
+        // Half a stick is not enough.
+        assert_eq!(buttons(frame(0.5, -0.5, None, false)), 0);
+        assert_eq!(
+            buttons(frame(0.8, -0.8, None, true)),
+            VersusInput::FORWARD | VersusInput::RIGHT | VersusInput::FIRE
+        );
+
+        let aim =
+            |angle: f32| VersusInput::from_frame(frame(0.0, 0.0, Some(angle), false), none).aim;
+        assert_eq!(aim(0.0), 0);
+        assert_eq!(aim(PI / 2.0), 16384);
+        assert_eq!(aim(PI), 32768);
+        // Negative headings wrap around the turn.
+        assert_eq!(aim(-PI / 2.0), 49152);
+        assert_eq!(aim(3.0 * PI / 2.0), 49152);
+        for &angle in [0.3, -2.0, 3.0].iter() {
+            let input = VersusInput::from_frame(frame(0.0, 0.0, Some(angle), false), none);
+            assert!(normalize_angle(input.aim_angle() - angle).abs() < 2.0 * PI / 65536.0);
+        }
+
+        // Without aim, the previous heading is kept.
+        let previous = VersusInput {
+            buttons: VersusInput::FIRE,
+            aim: 1234,
+        };
+        let input = VersusInput::from_frame(frame(1.0, 0.0, None, false), previous);
+        assert_eq!(input.aim, 1234);
+        assert_eq!(input.buttons, VersusInput::FORWARD);
+    }
+
+    #[test]
+    fn delayed_peers_stay_in_sync() {
+        let localhost = SocketAddr::from(([127, 0, 0, 1], 0));
+        let socket = bind_socket(localhost).unwrap();
+        let peer_socket = bind_socket(localhost).unwrap();
+        let address = socket.local_addr().unwrap();
+        let peer_address = peer_socket.local_addr().unwrap();
+        let mut session = RollbackSession::new(socket, peer_address, test_arena_size(), 0);
+        // Inputs of the peer arrive late, so the session has to predict and roll back.
+        let mut peer = RollbackSession::new(peer_socket, address, test_arena_size(), 5);
+        for _ in 0..600 {
+            session.pending_input = loopback_bot_input(&session);
+            peer.pending_input = loopback_bot_input(&peer);
+            session.update(VERSUS_DT);
+            peer.update(VERSUS_DT);
+            thread::sleep(Duration::from_millis(1));
+        }
+        for side in [&session, &peer].iter() {
+            // Checksums of the confirmed frames were compared along the way.
+            assert!(side.confirmed > 300);
+            assert_eq!(side.desync, None);
+        }
+        assert_ne!(session.local_side, peer.local_side);
+        assert!(session.rollbacks > 0);
+    }
+}
@@ -0,0 +1,228 @@
+use super::*;
+use serde::{Deserialize, Serialize};
+
+const POINTS_PER_XP: u32 = 10;
+/// Points multiplier gained per wave, 1.0 on the first one.
+const WAVE_MULTIPLIER: f32 = 0.25;
+/// Bonus per wave number when a wave is cleared without damage.
+const NO_DAMAGE_BONUS: u32 = 50;
+const HIGH_SCORES_VERSION: u32 = 1;
+const HIGH_SCORES_FILE: &str = "highscores.ron";
+pub const HIGH_SCORES_COUNT: usize = 10;
+const NAME_MAX_LENGTH: usize = 12;
+
+/// Statistics of the current run.
+#[derive(Default, Debug)]
+pub struct RunStats {
+    pub kills: u32,
+    pub score: u32,
+    /// Player was hit during the current wave.
+    pub wave_damage_taken: bool,
+}
+
+impl RunStats {
+    pub fn enemy_killed(&mut self, enemy: &Enemy, wave: u32) {
+        let multiplier = 1.0 + WAVE_MULTIPLIER * (wave - 1) as f32;
+        self.kills += 1;
+        self.score += (enemy.xp as f32 * POINTS_PER_XP as f32 * multiplier).round() as u32;
+    }
+    pub fn wave_cleared(&mut self, wave: u32) {
+        if !self.wave_damage_taken {
+            self.score += NO_DAMAGE_BONUS * wave;
+        }
+        self.wave_damage_taken = false;
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct HighScore {
+    pub name: String,
+    pub score: u32,
+    pub wave: u32,
+}
+
+/// Best runs, sorted from the highest score.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct HighScores {
+    pub version: u32,
+    pub entries: Vec<HighScore>,
+}
+
+impl Default for HighScores {
+    fn default() -> HighScores {
+        HighScores {
+            version: HIGH_SCORES_VERSION,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl HighScores {
+    /// Load the table from the user data directory.
+    /// A missing or corrupt file gives an empty table.
+    pub fn load() -> HighScores {
+        let mut high_scores = load_user_data::<HighScores>(HIGH_SCORES_FILE);
+        high_scores.version = HIGH_SCORES_VERSION;
+        high_scores.entries.sort_by(|a, b| b.score.cmp(&a.score));
+        high_scores.entries.truncate(HIGH_SCORES_COUNT);
+        high_scores
+    }
+    pub fn save(&self) {
+        save_user_data(HIGH_SCORES_FILE, self);
+    }
+    /// The score would enter the table.
+    pub fn qualifies(&self, score: u32) -> bool {
+        score > 0
+            && (self.entries.len() < HIGH_SCORES_COUNT
+                || self.entries.iter().any(|entry| score > entry.score))
+    }
+    /// Insert the entry at its rank, dropping the last one if the table is full.
+    pub fn insert(&mut self, entry: HighScore) -> Option<usize> {
+        if !self.qualifies(entry.score) {
+            return None;
+        }
+        let rank = self
+            .entries
+            .iter()
+            .position(|other| entry.score > other.score)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(rank, entry);
+        self.entries.truncate(HIGH_SCORES_COUNT);
+        Some(rank)
+    }
+}
+
+/// Name typed on the game over screen for the high score table.
+#[derive(Default)]
+pub struct NameEntry {
+    pub name: String,
+    pub submitted: bool,
+}
+
+impl NameEntry {
+    /// The name is being typed, the keyboard belongs to it.
+    pub fn is_active(&self, state: GameState, score: u32, high_scores: &HighScores) -> bool {
+        state == GameState::GameOver && !self.submitted && high_scores.qualifies(score)
+    }
+}
+
+/// Award the no damage bonus on wave clear.
+pub fn score_wave_system(mut was_cleared: Local<bool>, wave: Res<Wave>, mut run: ResMut<RunStats>) {
+    if wave.is_cleared() && !*was_cleared {
+        run.wave_cleared(wave.number);
+    }
+    *was_cleared = wave.is_cleared();
+}
+
+/// Type the name on game over, Return adds the run to the high score table.
+pub fn name_entry_system(
+    mut char_reader: Local<EventReader<ReceivedCharacter>>,
+    char_events: Res<Events<ReceivedCharacter>>,
+    keyboard_input: Res<Input<KeyCode>>,
+    state: Res<GameState>,
+    run: Res<RunStats>,
+    wave: Res<Wave>,
+    mut name_entry: ResMut<NameEntry>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    let chars = char_reader
+        .iter(&char_events)
+        .map(|event| event.char)
+        .collect::<Vec<_>>();
+    if !name_entry.is_active(*state, run.score, &high_scores) {
+        return;
+    }
+    for c in chars {
+        if (c.is_alphanumeric() || c == ' ') && name_entry.name.chars().count() < NAME_MAX_LENGTH {
+            name_entry.name.push(c);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Back) {
+        name_entry.name.pop();
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) {
+        let name = name_entry.name.trim();
+        high_scores.insert(HighScore {
+            name: if name.is_empty() { "Pilot" } else { name }.to_string(),
+            score: run.score,
+            wave: wave.number,
+        });
+        high_scores.save();
+        name_entry.submitted = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(name: &str, score: u32) -> HighScore {
+        HighScore {
+            name: name.to_string(),
+            score,
+            wave: 1,
+        }
+    }
+
+    /// Table full of the scores 10, 20, ..., 100.
+    fn full_table() -> HighScores {
+        let mut high_scores = HighScores::default();
+        for i in 1..=HIGH_SCORES_COUNT as u32 {
+            high_scores.insert(entry("full", i * 10));
+        }
+        high_scores
+    }
+
+    fn scores(high_scores: &HighScores) -> Vec<u32> {
+        high_scores
+            .entries
+            .iter()
+            .map(|entry| entry.score)
+            .collect()
+    }
+
+    #[test]
+    fn zero_never_qualifies() {
+        let high_scores = HighScores::default();
+        assert!(!high_scores.qualifies(0));
+        assert!(high_scores.qualifies(1));
+    }
+
+    #[test]
+    fn full_table_needs_a_better_score() {
+        let high_scores = full_table();
+        assert_eq!(high_scores.entries.len(), HIGH_SCORES_COUNT);
+        assert!(!high_scores.qualifies(5));
+        assert!(!high_scores.qualifies(10));
+        assert!(high_scores.qualifies(11));
+    }
+
+    #[test]
+    fn insert_keeps_the_table_sorted() {
+        let mut high_scores = HighScores::default();
+        assert_eq!(high_scores.insert(entry("a", 50)), Some(0));
+        assert_eq!(high_scores.insert(entry("b", 200)), Some(0));
+        assert_eq!(high_scores.insert(entry("c", 100)), Some(1));
+        assert_eq!(scores(&high_scores), vec![200, 100, 50]);
+    }
+
+    #[test]
+    fn tie_ranks_after_the_existing_entry() {
+        let mut high_scores = HighScores::default();
+        high_scores.insert(entry("first", 100));
+        assert_eq!(high_scores.insert(entry("second", 100)), Some(1));
+        assert_eq!(high_scores.entries[0].name, "first");
+    }
+
+    #[test]
+    fn insert_drops_the_last_entry_when_full() {
+        let mut high_scores = full_table();
+        assert_eq!(high_scores.insert(entry("new", 55)), Some(5));
+        assert_eq!(high_scores.entries.len(), HIGH_SCORES_COUNT);
+        assert_eq!(high_scores.entries[5].name, "new");
+        assert_eq!(high_scores.entries.last().unwrap().score, 20);
+        assert_eq!(high_scores.insert(entry("low", 15)), None);
+        assert_eq!(high_scores.entries.len(), HIGH_SCORES_COUNT);
+    }
+}
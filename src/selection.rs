@@ -22,7 +22,7 @@ pub fn show_selection_system(
     selection_changed_events: Res<Events<CursorSelectionEvent>>,
     mut materials: ResMut<Assets<OutlineMaterial>>,
     handles: Query<&Handle<OutlineMaterial>>,
-    mut texts: Query<Mut<Text>>,
+    mut texts: Query<With<SelectionText, Mut<Text>>>,
 ) {
     for event in state.iter(&selection_changed_events) {
         change_outline(&mut materials, &handles, &event.prev_enemies, false);
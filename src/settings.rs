@@ -0,0 +1,285 @@
+use std::collections::BTreeMap;
+
+use super::*;
+use bevy_prototype_input_map::InputMap;
+use serde::{Deserialize, Serialize};
+
+const SETTINGS_VERSION: u32 = 1;
+const SETTINGS_FILE: &str = "settings.ron";
+/// Opens and closes the settings, not rebindable so it can't be lost.
+const SETTINGS_KEY: KeyCode = KeyCode::F1;
+
+/// Player preferences, kept across runs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct Settings {
+    pub version: u32,
+    pub bindings: Bindings,
+    pub control_scheme: ControlScheme,
+    pub audio: AudioSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            version: SETTINGS_VERSION,
+            bindings: default_bindings(),
+            control_scheme: ControlScheme::CursorAim,
+            audio: AudioSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    /// Load the settings from the user data directory.
+    /// A missing or corrupt file gives the default settings.
+    pub fn load() -> Settings {
+        load_user_data::<Settings>(SETTINGS_FILE).migrate()
+    }
+    pub fn save(&self) {
+        save_user_data(SETTINGS_FILE, self);
+    }
+    fn migrate(mut self) -> Settings {
+        self.version = SETTINGS_VERSION;
+        // Actions added since the file was saved get their default bindings.
+        for (action, inputs) in default_bindings() {
+            self.bindings.entry(action).or_insert(inputs);
+        }
+        for (binding, actions) in binding_conflicts(&self.bindings) {
+            println!(
+                "{} is bound to several actions: {}",
+                binding.name(),
+                actions.join(", ")
+            );
+        }
+        self
+    }
+    /// Action other than the given one using the binding.
+    fn bound_elsewhere(&self, action: &str, binding: Binding) -> Option<&str> {
+        self.bindings
+            .iter()
+            .find(|(other, inputs)| other.as_str() != action && inputs.contains(&binding))
+            .map(|(other, _)| other.as_str())
+    }
+}
+
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum CaptureMode {
+    Replace,
+    Add,
+}
+
+/// State of the settings screen.
+#[derive(Default)]
+pub struct SettingsMenu {
+    pub selected: usize,
+    /// Waiting for the next key or mouse button, for the selected action.
+    pub capture: Option<CaptureMode>,
+    pub message: String,
+}
+
+impl SettingsMenu {
+    /// Rows are the actions, then the volumes.
+    fn row_count() -> usize {
+        ACTIONS.len() + VolumeBus::ALL.len()
+    }
+    fn action(&self) -> Option<&'static str> {
+        ACTIONS.get(self.selected).copied()
+    }
+    fn volume_bus(&self) -> Option<VolumeBus> {
+        self.selected
+            .checked_sub(ACTIONS.len())
+            .and_then(|index| VolumeBus::ALL.get(index).copied())
+    }
+}
+
+fn next_binding(
+    keyboard_input: &Input<KeyCode>,
+    mouse_input: &Input<MouseButton>,
+) -> Option<Binding> {
+    keyboard_input
+        .get_just_pressed()
+        .map(|&key| Binding::Key(key))
+        .chain(
+            mouse_input
+                .get_just_pressed()
+                .map(|&button| Binding::Mouse(button)),
+        )
+        .find(|binding| binding.is_bindable())
+}
+
+pub fn settings_system(
+    mut state: ResMut<GameState>,
+    mut settings: ResMut<Settings>,
+    mut menu: ResMut<SettingsMenu>,
+    mut input_map: ResMut<InputMap>,
+    keyboard_input: Res<Input<KeyCode>>,
+    mouse_input: Res<Input<MouseButton>>,
+) {
+    if *state == GameState::Playing && keyboard_input.just_pressed(SETTINGS_KEY) {
+        *state = GameState::Settings;
+        *menu = SettingsMenu::default();
+        return;
+    }
+    if *state != GameState::Settings {
+        return;
+    }
+    if let (Some(mode), Some(action)) = (menu.capture, menu.action()) {
+        if keyboard_input.just_pressed(KeyCode::Escape) {
+            menu.capture = None;
+            menu.message = String::from("Cancelled");
+        } else if let Some(binding) = next_binding(&keyboard_input, &mouse_input) {
+            menu.capture = None;
+            if let Some(other) = settings.bound_elsewhere(action, binding) {
+                menu.message = format!("{} is already bound to {}", binding.name(), other);
+                return;
+            }
+            let inputs = settings
+                .bindings
+                .entry(action.to_string())
+                .or_insert_with(Vec::new);
+            if mode == CaptureMode::Replace {
+                inputs.clear();
+            }
+            if !inputs.contains(&binding) {
+                inputs.push(binding);
+            }
+            menu.message = format!("{} bound to {}", binding.name(), action);
+            apply_bindings(&mut input_map, &settings.bindings);
+        }
+        return;
+    }
+    let row_count = SettingsMenu::row_count();
+    if keyboard_input.just_pressed(KeyCode::Up) {
+        menu.selected = (menu.selected + row_count - 1) % row_count;
+    }
+    if keyboard_input.just_pressed(KeyCode::Down) {
+        menu.selected = (menu.selected + 1) % row_count;
+    }
+    if let Some(action) = menu.action() {
+        if keyboard_input.just_pressed(KeyCode::Return) {
+            menu.capture = Some(CaptureMode::Replace);
+            menu.message = format!("Press a key or mouse button for {}", action);
+        }
+        if keyboard_input.just_pressed(KeyCode::Tab) {
+            menu.capture = Some(CaptureMode::Add);
+            menu.message = format!("Press an extra key or mouse button for {}", action);
+        }
+        if keyboard_input.just_pressed(KeyCode::Delete) {
+            settings.bindings.insert(action.to_string(), Vec::new());
+            menu.message = format!("{} unbound", action);
+            apply_bindings(&mut input_map, &settings.bindings);
+        }
+    }
+    if let Some(bus) = menu.volume_bus() {
+        if keyboard_input.just_pressed(KeyCode::Left) {
+            settings.audio.change_volume(bus, -VOLUME_STEP);
+        }
+        if keyboard_input.just_pressed(KeyCode::Right) {
+            settings.audio.change_volume(bus, VOLUME_STEP);
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::C) {
+        settings.control_scheme = settings.control_scheme.next();
+        menu.message = format!("Control scheme: {}", settings.control_scheme.name());
+    }
+    if keyboard_input.just_pressed(KeyCode::R) {
+        settings.bindings = default_bindings();
+        menu.message = String::from("Default bindings restored");
+        apply_bindings(&mut input_map, &settings.bindings);
+    }
+    if keyboard_input.just_pressed(KeyCode::Escape) || keyboard_input.just_pressed(SETTINGS_KEY) {
+        settings.save();
+        *state = GameState::Playing;
+    }
+}
+
+/// Tag component for the settings panel root node.
+pub struct SettingsPanel;
+pub enum SettingsText {
+    Title,
+    ControlScheme,
+    Action(usize),
+    Volume(VolumeBus),
+    Message,
+    Help,
+}
+
+/// Show the settings panel while in GameState::Settings.
+pub fn settings_ui_system(
+    mut commands: Commands,
+    state: Res<GameState>,
+    settings: Res<Settings>,
+    menu: Res<SettingsMenu>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    panels: Query<With<SettingsPanel, Entity>>,
+    mut texts: Query<(&SettingsText, Mut<Text>)>,
+) {
+    let panel = panels.iter().next();
+    if *state != GameState::Settings {
+        if let Some(entity) = panel {
+            commands.despawn_recursive(entity);
+        }
+        return;
+    }
+    if panel.is_none() {
+        let lines = vec![SettingsText::Title, SettingsText::ControlScheme]
+            .into_iter()
+            .chain((0..ACTIONS.len()).map(SettingsText::Action))
+            .chain(VolumeBus::ALL.iter().map(|&bus| SettingsText::Volume(bus)))
+            .chain(vec![SettingsText::Message, SettingsText::Help])
+            .collect();
+        let font = asset_server.load("FiraSans-Bold.ttf");
+        spawn_panel(&mut commands, &mut materials, font, SettingsPanel, lines);
+        return;
+    }
+    let conflicts: BTreeMap<String, Vec<String>> = binding_conflicts(&settings.bindings)
+        .into_iter()
+        .flat_map(|(binding, actions)| {
+            actions
+                .into_iter()
+                .map(move |action| (action, binding.name()))
+        })
+        .fold(BTreeMap::new(), |mut conflicts, (action, name)| {
+            conflicts.entry(action).or_insert_with(Vec::new).push(name);
+            conflicts
+        });
+    for (line, mut text) in texts.iter_mut() {
+        text.value = match line {
+            SettingsText::Title => String::from("Controls"),
+            SettingsText::ControlScheme => {
+                format!("Scheme (C to change): {}", settings.control_scheme.name())
+            }
+            SettingsText::Action(index) => {
+                let action = ACTIONS[*index];
+                let inputs = settings
+                    .bindings
+                    .get(action)
+                    .map(|inputs| inputs.iter().map(Binding::name).collect::<Vec<_>>())
+                    .unwrap_or_default();
+                let cursor = if *index == menu.selected { ">" } else { " " };
+                let conflict = match conflicts.get(action) {
+                    Some(names) => format!(" - conflict on {}", names.join(", ")),
+                    None => String::new(),
+                };
+                format!("{} {}: {}{}", cursor, action, inputs.join(", "), conflict)
+            }
+            SettingsText::Volume(bus) => {
+                let cursor = if menu.volume_bus() == Some(*bus) { ">" } else { " " };
+                let muted = if settings.audio.muted { " (muted, M)" } else { "" };
+                format!(
+                    "{} {}: {:.0}%{}",
+                    cursor,
+                    bus.name(),
+                    settings.audio.volume(*bus) * 100.0,
+                    muted
+                )
+            }
+            SettingsText::Message => menu.message.clone(),
+            SettingsText::Help => String::from(
+                "Up/Down select, Return rebind, Tab add, Delete clear, Left/Right volume, C scheme, R defaults, Escape back",
+            ),
+        };
+    }
+}
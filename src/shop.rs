@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use super::*;
+
+/// Price multiplier applied after each purchase of the same upgrade.
+const PRICE_GROWTH: f32 = 1.5;
+
+#[derive(Eq, PartialEq, Hash, Copy, Clone, Debug)]
+pub enum Upgrade {
+    Engine,
+    Thrusters,
+    FireRate,
+    MunitionLifespan,
+    Armor,
+}
+
+impl Upgrade {
+    pub const ALL: [Upgrade; 5] = [
+        Upgrade::Engine,
+        Upgrade::Thrusters,
+        Upgrade::FireRate,
+        Upgrade::MunitionLifespan,
+        Upgrade::Armor,
+    ];
+    pub fn name(&self) -> &'static str {
+        match self {
+            Upgrade::Engine => "Engine",
+            Upgrade::Thrusters => "Lateral thrusters",
+            Upgrade::FireRate => "Fire rate",
+            Upgrade::MunitionLifespan => "Munition lifespan",
+            Upgrade::Armor => "Armor",
+        }
+    }
+    fn base_price(&self) -> u32 {
+        match self {
+            Upgrade::Engine => 20,
+            Upgrade::Thrusters => 15,
+            Upgrade::FireRate => 30,
+            Upgrade::MunitionLifespan => 20,
+            Upgrade::Armor => 25,
+        }
+    }
+    /// Price of the next purchase, knowing how many were already bought.
+    pub fn price(&self, purchased: u32) -> u32 {
+        (self.base_price() as f32 * PRICE_GROWTH.powi(purchased as i32)).round() as u32
+    }
+    pub fn apply(&self, ship: &mut Spaceship, weapon: &mut Weapon, armor: &mut Armor) {
+        match self {
+            Upgrade::Engine => ship.max_linvel *= 1.1,
+            Upgrade::Thrusters => ship.max_latvel *= 1.15,
+            Upgrade::FireRate => weapon.fire_timer.duration *= 0.9,
+            Upgrade::MunitionLifespan => weapon.munition_lifespan *= 1.1,
+            Upgrade::Armor => {
+                armor.max_life += 1;
+                armor.life += 1;
+            }
+        }
+    }
+}
+
+/// Number of purchases per upgrade of a ship.
+#[derive(Default)]
+pub struct Upgrades(pub HashMap<Upgrade, u32>);
+
+impl Upgrades {
+    pub fn purchased(&self, upgrade: Upgrade) -> u32 {
+        *self.0.get(&upgrade).unwrap_or(&0)
+    }
+}
+
+const SHOP_KEYS: [KeyCode; 5] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+];
+
+/// Upgrade highlighted for the gamepad player, bought with the south button.
+#[derive(Default)]
+pub struct ShopCursor(pub usize);
+
+/// Each player buys upgrades for their ship with their own device: number keys,
+/// or the d-pad and south button of the gamepad. Start the next wave with Return or Start.
+pub fn shop_system(
+    mut state: ResMut<GameState>,
+    mut wave: ResMut<Wave>,
+    keyboard_input: Res<Input<KeyCode>>,
+    (gamepad_state, gamepad_buttons, mut cursor): (
+        Res<GamepadState>,
+        Res<Input<GamepadButton>>,
+        ResMut<ShopCursor>,
+    ),
+    mut ships: Query<
+        With<
+            UserControlled,
+            (
+                &InputDevice,
+                Mut<Inventory>,
+                Mut<Upgrades>,
+                Mut<Spaceship>,
+                Mut<Weapon>,
+                Mut<Armor>,
+            ),
+        >,
+    >,
+) {
+    if *state != GameState::Shop {
+        return;
+    }
+    let gamepad_pressed = |button_type| gamepad_state.just_pressed(&gamepad_buttons, button_type);
+    let count = Upgrade::ALL.len();
+    if gamepad_pressed(GamepadButtonType::DPadDown) {
+        cursor.0 = (cursor.0 + 1) % count;
+    }
+    if gamepad_pressed(GamepadButtonType::DPadUp) {
+        cursor.0 = (cursor.0 + count - 1) % count;
+    }
+    let keyboard_upgrade = SHOP_KEYS
+        .iter()
+        .zip(Upgrade::ALL.iter())
+        .find(|(key, _)| keyboard_input.just_pressed(**key))
+        .map(|(_, &upgrade)| upgrade);
+    let gamepad_upgrade = if gamepad_pressed(GamepadButtonType::South) {
+        Some(Upgrade::ALL[cursor.0])
+    } else {
+        None
+    };
+    for (device, mut inventory, mut upgrades, mut ship, mut weapon, mut armor) in ships.iter_mut() {
+        let upgrade = match device {
+            InputDevice::Auto => keyboard_upgrade.or(gamepad_upgrade),
+            InputDevice::KeyboardMouse => keyboard_upgrade,
+            InputDevice::Gamepad => gamepad_upgrade,
+            // Networked clients have no shop controls.
+            InputDevice::Remote => None,
+        };
+        if let Some(upgrade) = upgrade {
+            let price = upgrade.price(upgrades.purchased(upgrade));
+            if inventory.spend(price) {
+                upgrade.apply(&mut ship, &mut weapon, &mut armor);
+                *upgrades.0.entry(upgrade).or_insert(0) += 1;
+            }
+        }
+    }
+    if keyboard_input.just_pressed(KeyCode::Return) || gamepad_pressed(GamepadButtonType::Start) {
+        wave.next();
+        *state = GameState::Playing;
+    }
+}
+
+/// Tag component for the shop panel root node.
+pub struct ShopPanel;
+/// Tag component for the text of one upgrade line, None for the title.
+pub struct ShopText(Option<Upgrade>);
+
+/// Show the shop panel while in GameState::Shop.
+pub fn shop_ui_system(
+    mut commands: Commands,
+    state: Res<GameState>,
+    wave: Res<Wave>,
+    asset_server: Res<AssetServer>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+    (cursor, gamepad_state): (Res<ShopCursor>, Res<GamepadState>),
+    panels: Query<With<ShopPanel, Entity>>,
+    ships: Query<With<UserControlled, (&LocalPlayer, &InputDevice, &Inventory, &Upgrades)>>,
+    mut texts: Query<(&ShopText, Mut<Text>)>,
+) {
+    let panel = panels.iter().next();
+    if *state != GameState::Shop {
+        if let Some(entity) = panel {
+            commands.despawn_recursive(entity);
+        }
+        return;
+    }
+    if panel.is_none() {
+        let lines = std::iter::once(None)
+            .chain(Upgrade::ALL.iter().map(|&u| Some(u)))
+            .map(ShopText)
+            .collect();
+        let font = asset_server.load("FiraSans-Bold.ttf");
+        spawn_panel(&mut commands, &mut materials, font, ShopPanel, lines);
+        return;
+    }
+    let mut ships: Vec<_> = ships.iter().collect();
+    if ships.is_empty() {
+        return;
+    }
+    ships.sort_by_key(|(player, _, _, _)| player.0);
+    // Each line lists every player in co-op, prefixed by their number.
+    let co_op = ships.len() > 1;
+    let player_prefix = |player: &LocalPlayer| {
+        if co_op {
+            format!("P{} ", player.0 + 1)
+        } else {
+            "".to_string()
+        }
+    };
+    let gamepad_shopping = ships
+        .iter()
+        .any(|(_, device, _, _)| device.uses_gamepad(&gamepad_state));
+    for (line, mut text) in texts.iter_mut() {
+        text.value = match line.0 {
+            None => {
+                let credits = ships
+                    .iter()
+                    .map(|&(player, _, inventory, _)| {
+                        format!("{}{} credits", player_prefix(player), inventory.credits)
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "Wave {} cleared - {} - {} to continue",
+                    wave.number,
+                    credits.join(", "),
+                    if gamepad_shopping {
+                        "Return or Start"
+                    } else {
+                        "Return"
+                    }
+                )
+            }
+            Some(upgrade) => {
+                let index = Upgrade::ALL.iter().position(|&u| u == upgrade).unwrap();
+                let marker = if gamepad_shopping && index == cursor.0 {
+                    "> "
+                } else {
+                    ""
+                };
+                let levels = ships
+                    .iter()
+                    .map(|&(player, _, _, upgrades)| {
+                        let purchased = upgrades.purchased(upgrade);
+                        format!(
+                            "{}level {}, {} credits",
+                            player_prefix(player),
+                            purchased,
+                            upgrade.price(purchased)
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}{}: {} - {}",
+                    marker,
+                    index + 1,
+                    upgrade.name(),
+                    levels.join(" / ")
+                )
+            }
+        };
+    }
+}
@@ -1,78 +1,133 @@
 use super::*;
+use serde::{Deserialize, Serialize};
+
 pub struct Spaceship {
     pub max_angvel: f32,
+    pub max_angacc: f32,
     pub max_linvel: f32,
     pub max_latvel: f32,
 }
 pub struct Weapon {
     pub fire_timer: Timer,
     pub munition_lifespan: f32,
+    pub damage: u32,
 }
-impl Spaceship {
-    /// Compute the speed to reach world coordinate, within ship limits.
-    pub fn velocity_to(
-        &self,
-        ship_transform: &Transform,
-        world_x: f32,
-        world_y: f32,
-        delta_seconds: f32,
-    ) -> Vec2 {
-        let (ship_vec, mut ship_angle) = ship_transform.rotation.to_axis_angle();
-        // ship_vec can be Z or -Z;
-        let delta_x = world_x - ship_transform.translation.x();
-        let delta_y = world_y - ship_transform.translation.y();
-        ship_angle = ship_angle * ship_vec.z();
-        let max_angvel = self.max_angvel * delta_seconds;
-        let delta_angle = Vec2::new(ship_angle.cos(), ship_angle.sin())
-            .angle_between(Vec2::new(delta_x, delta_y))
-            .max(-max_angvel)
-            .min(max_angvel);
-        let new_angle = ship_angle + delta_angle;
-        let velocity = Vec2::new(new_angle.cos(), new_angle.sin()) * self.max_linvel;
-        velocity
-    }
+
+/// Angle of the rotation around Z, in ]-PI, PI].
+pub fn rotation_angle(rotation: &Quat) -> f32 {
+    let (axis, angle) = rotation.to_axis_angle();
+    // axis can be Z or -Z;
+    normalize_angle(angle * axis.z())
 }
 
-pub struct XpEvent {
-    pub xp: u32,
-    pub source: Entity,
+/// Bring the angle back in ]-PI, PI].
+pub fn normalize_angle(angle: f32) -> f32 {
+    let angle = angle % (2.0 * PI);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle <= -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
 }
-pub struct Progression {
-    pub level: u32,
-    pub xp: u32,
+
+impl Spaceship {
+    /// Compute the angular velocity to face target_angle, within ship limits.
+    /// Braking is anticipated, so the ship stops on the target angle.
+    pub fn steer(&self, angvel: f32, angle: f32, target_angle: f32, delta_seconds: f32) -> f32 {
+        let delta_angle = normalize_angle(target_angle - angle);
+        let mut wanted_angvel = delta_angle.signum()
+            * (2.0 * self.max_angacc * delta_angle.abs())
+                .sqrt()
+                .min(self.max_angvel);
+        if delta_seconds > 0.0 && (wanted_angvel * delta_seconds).abs() > delta_angle.abs() {
+            // Close enough to reach the target this frame.
+            wanted_angvel = delta_angle / delta_seconds;
+        }
+        self.accelerate_rotation(angvel, wanted_angvel, delta_seconds)
+    }
+    /// Move the angular velocity toward the wanted one, within ship angular acceleration.
+    pub fn accelerate_rotation(&self, angvel: f32, wanted_angvel: f32, delta_seconds: f32) -> f32 {
+        let wanted_angvel = wanted_angvel.max(-self.max_angvel).min(self.max_angvel);
+        let max_change = self.max_angacc * delta_seconds;
+        angvel + (wanted_angvel - angvel).max(-max_change).min(max_change)
+    }
 }
 
-const XP_MAX_LEVEL: u32 = 6;
-const XP_PER_LEVEL: &[u32] = &[0, 10, 30, 100, 300, 1000, 0];
+/// Playable ships, unlocked by reaching waves.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Copy, Clone, Debug)]
+pub enum ShipClass {
+    Fighter,
+    Interceptor,
+    Gunship,
+}
 
-impl Progression {
-    pub fn new() -> Progression {
-        Progression { level: 1, xp: 0 }
+impl ShipClass {
+    pub const ALL: [ShipClass; 3] = [
+        ShipClass::Fighter,
+        ShipClass::Interceptor,
+        ShipClass::Gunship,
+    ];
+    pub fn spaceship(&self) -> Spaceship {
+        match self {
+            ShipClass::Fighter => Spaceship {
+                max_angvel: 2.0 * PI,
+                max_angacc: 8.0 * PI,
+                max_linvel: 1000.0,
+                max_latvel: 300.0,
+            },
+            ShipClass::Interceptor => Spaceship {
+                max_angvel: 3.0 * PI,
+                max_angacc: 14.0 * PI,
+                max_linvel: 1300.0,
+                max_latvel: 450.0,
+            },
+            ShipClass::Gunship => Spaceship {
+                max_angvel: 1.5 * PI,
+                max_angacc: 4.0 * PI,
+                max_linvel: 800.0,
+                max_latvel: 250.0,
+            },
+        }
     }
-    pub fn add_xp(&mut self, xp: u32) {
-        if self.level == XP_MAX_LEVEL {
-            return;
+    pub fn armor(&self) -> Armor {
+        match self {
+            ShipClass::Fighter => Armor::new(5),
+            ShipClass::Interceptor => Armor::new(3),
+            ShipClass::Gunship => Armor::new(8),
         }
-        self.xp += xp;
-        if self.xp >= XP_PER_LEVEL[self.level as usize] {
-            self.xp -= XP_PER_LEVEL[self.level as usize];
-            self.level += 1;
+    }
+    pub fn color(&self) -> Color {
+        match self {
+            ShipClass::Fighter => Color::WHITE,
+            ShipClass::Interceptor => Color::rgb(0.6, 0.8, 1.0),
+            ShipClass::Gunship => Color::rgb(1.0, 0.8, 0.5),
         }
-        println!(
-            "Level {} ; {}/{}",
-            self.level, self.xp, XP_PER_LEVEL[self.level as usize]
-        );
     }
-}
-
-pub fn xp_system(
-    mut xp_event_reader: Local<EventReader<XpEvent>>,
-    xp_events: Res<Events<XpEvent>>,
-    mut progressions: Query<Mut<Progression>>,
-) {
-    for event in xp_event_reader.iter(&*xp_events) {
-        if let Ok(mut progression) = progressions.get_component_mut::<Progression>(event.source) {
-            progression.add_xp(event.xp);
+    /// Highest wave to reach in a previous run to unlock the ship.
+    pub fn unlock_wave(&self) -> u32 {
+        match self {
+            ShipClass::Fighter => 0,
+            ShipClass::Interceptor => 5,
+            ShipClass::Gunship => 10,
+        }
+    }
+    /// Meta currency spent to unlock the ship before reaching its wave.
+    pub fn unlock_cost(&self) -> u32 {
+        match self {
+            ShipClass::Fighter => 0,
+            ShipClass::Interceptor => 200,
+            ShipClass::Gunship => 500,
+        }
+    }
+    /// Name given to --ship.
+    pub fn from_name(name: &str) -> Option<ShipClass> {
+        match name {
+            "fighter" => Some(ShipClass::Fighter),
+            "interceptor" => Some(ShipClass::Interceptor),
+            "gunship" => Some(ShipClass::Gunship),
+            _ => None,
         }
     }
 }
@@ -83,8 +138,13 @@ pub fn spawn_player_spaceship(
     mut materials: ResMut<Assets<ColorMaterial>>,
     mut collide_world: ResMut<CollisionWorld<f32, Entity>>,
     collide_groups: Res<CollideGroups>,
+    (profile, local_players, network): (Res<Profile>, Res<LocalPlayers>, Res<Network>),
     cameras: Query<(Entity, &Camera)>,
 ) {
+    // The ships are only spawned where the waves are simulated.
+    if !network.simulates_waves() {
+        return;
+    }
     let camera_entity = cameras
         .iter()
         .filter_map(|(entity, camera)| {
@@ -96,39 +156,164 @@ pub fn spawn_player_spaceship(
         })
         .next()
         .unwrap();
+    for player in 0..local_players.0 {
+        // Side by side in co-op, the first player uses the keyboard and mouse.
+        let position = Vec2::new(
+            (player as f32 - (local_players.0 - 1) as f32 / 2.0) * 100.0,
+            0.0,
+        );
+        let device = match (local_players.0, player) {
+            (1, _) => InputDevice::Auto,
+            (_, 0) => InputDevice::KeyboardMouse,
+            _ => InputDevice::Gamepad,
+        };
+        let entity = spawn_ship(
+            &mut commands,
+            &asset_server,
+            &mut materials,
+            &mut collide_world,
+            &collide_groups,
+            profile.selected_ship,
+            position,
+        );
+        commands.insert(
+            entity,
+            (device, FollowedCamera(camera_entity), LocalPlayer(player)),
+        );
+    }
+}
+
+/// Spawn a player ship with its collider, the caller adds the input device.
+pub fn spawn_ship(
+    commands: &mut Commands,
+    asset_server: &AssetServer,
+    materials: &mut Assets<ColorMaterial>,
+    collide_world: &mut CollisionWorld<f32, Entity>,
+    collide_groups: &CollideGroups,
+    ship_class: ShipClass,
+    position: Vec2,
+) -> Entity {
     commands
         .spawn(SpriteComponents {
-            material: materials.add(asset_server.load("playerShip1_red.png").into()),
+            material: materials.add(ColorMaterial::modulated_texture(
+                asset_server.load("playerShip1_red.png"),
+                ship_class.color(),
+            )),
             //sprite: Sprite::new(Vec2::new(33.0 * 2.0, 33.0 * 2.0)),
             //material: materials.add(Color::rgb(0.5, 0.5, 1.0).into()),
-            transform: Transform::from_scale(Vec3::splat(0.3)),
+            transform: Transform {
+                translation: Vec3::new(position.x(), position.y(), 0.0),
+                scale: Vec3::splat(0.3),
+                ..Default::default()
+            },
             ..Default::default()
         })
         .with(UserControlled {})
         .with(Movement {
             speed: Vec2::zero(),
             dampening: 0.1,
+            angvel: 0.0,
+            mass: 1.0,
         })
-        .with(Spaceship {
-            max_angvel: 2.0 * PI,
-            max_linvel: 1000.0,
-            max_latvel: 300.0,
-        })
-        .with(FollowedCamera(camera_entity))
+        .with(ship_class.spaceship())
         .with(Weapon {
             fire_timer: Timer::from_seconds(0.5, false),
             munition_lifespan: 1.5,
+            damage: 1,
         })
         .with(Progression::new())
+        .with(Inventory::new())
+        .with(Upgrades::default())
+        .with(Perks::default())
+        .with(ship_class.armor())
+        .with(ParticleEmitter::new(
+            ParticleEffect::Exhaust,
+            60.0,
+            Vec2::new(-18.0, 0.0),
+            Vec2::new(-1.0, 0.0),
+        ))
         .with(ColliderType::Ship);
     let shape = ShapeHandle::new(Ball::new(99.0 * 0.3 * 0.5));
     let entity = commands.current_entity().unwrap();
     let (collision_object_handle, _) = collide_world.add(
-        Isometry2::new(Vector2::new(0.0, 0.0), na::zero()),
+        Isometry2::new(Vector2::new(position.x(), position.y()), na::zero()),
         shape,
         collide_groups.ships,
         GeometricQueryType::Contacts(0.0, 0.0),
         entity,
     );
     commands.insert(entity, (collision_object_handle,));
+    entity
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    fn ship() -> Spaceship {
+        Spaceship {
+            max_angvel: 4.0,
+            max_angacc: 8.0,
+            max_linvel: 400.0,
+            max_latvel: 200.0,
+        }
+    }
+
+    /// Steer for a few seconds, checking the limits on every frame.
+    /// Returns the final angle and angular velocity, and the largest overshoot.
+    fn steer_until_still(angle: f32, target_angle: f32) -> (f32, f32, f32) {
+        let ship = ship();
+        let direction = normalize_angle(target_angle - angle).signum();
+        let (mut angle, mut angvel, mut overshoot) = (angle, 0.0, 0.0f32);
+        for _ in 0..600 {
+            let new_angvel = ship.steer(angvel, angle, target_angle, DT);
+            assert!(new_angvel.abs() <= ship.max_angvel + 1e-4);
+            assert!((new_angvel - angvel).abs() <= ship.max_angacc * DT + 1e-4);
+            angvel = new_angvel;
+            angle = normalize_angle(angle + angvel * DT);
+            overshoot = overshoot.max(normalize_angle(angle - target_angle) * direction);
+        }
+        (angle, angvel, overshoot)
+    }
+
+    #[test]
+    fn steer_is_limited_by_angular_acceleration() {
+        assert!((ship().steer(0.0, 0.0, 3.0, 0.1) - 0.8).abs() < 1e-5);
+        assert!((ship().steer(0.0, 0.0, -3.0, 0.1) + 0.8).abs() < 1e-5);
+    }
+
+    #[test]
+    fn steer_is_limited_by_angular_velocity() {
+        assert_eq!(ship().steer(4.0, 0.0, 3.0, 0.1), 4.0);
+        assert_eq!(ship().accelerate_rotation(3.9, 100.0, 0.1), 4.0);
+    }
+
+    #[test]
+    fn steer_brakes_before_the_target() {
+        // At full speed, 0.5 rad is less than the braking distance of 1 rad.
+        let angvel = ship().steer(4.0, 0.0, 0.5, DT);
+        assert!(angvel < 4.0);
+        assert!((angvel - (4.0 - 8.0 * DT)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn steer_stops_on_the_target() {
+        let (angle, angvel, overshoot) = steer_until_still(0.0, 2.0);
+        assert!((angle - 2.0).abs() < 1e-3);
+        assert!(angvel.abs() < 1e-3);
+        assert!(overshoot < 0.05);
+    }
+
+    #[test]
+    fn steer_takes_the_short_way_across_pi() {
+        let ship = ship();
+        assert!(ship.steer(0.0, PI - 0.1, -PI + 0.1, DT) > 0.0);
+        assert!(ship.steer(0.0, -PI + 0.1, PI - 0.1, DT) < 0.0);
+        let (angle, angvel, overshoot) = steer_until_still(PI - 0.3, -PI + 0.3);
+        assert!(normalize_angle(angle - (-PI + 0.3)).abs() < 1e-3);
+        assert!(angvel.abs() < 1e-3);
+        assert!(overshoot < 0.05);
+    }
 }
@@ -0,0 +1,148 @@
+use rand::rngs::StdRng;
+
+use super::*;
+
+/// Side of the square repeated by each layer, larger than the view at the maximum zoom.
+/// Stretched when the camera zooms out further to keep the co-op players in view.
+const STAR_TILE_SIZE: f32 = 3000.0;
+/// Depth of the furthest layer, below every sprite of the arena.
+const STARFIELD_DEPTH: f32 = -20.0;
+
+struct StarLayer {
+    /// Fraction of the camera moves followed by the layer, lower is further.
+    parallax: f32,
+    count: usize,
+    size: f32,
+    brightness: f32,
+}
+
+const STAR_LAYERS: [StarLayer; 3] = [
+    StarLayer {
+        parallax: 0.1,
+        count: 250,
+        size: 1.5,
+        brightness: 0.35,
+    },
+    StarLayer {
+        parallax: 0.3,
+        count: 120,
+        size: 2.0,
+        brightness: 0.6,
+    },
+    StarLayer {
+        parallax: 0.6,
+        count: 50,
+        size: 3.0,
+        brightness: 0.9,
+    },
+];
+
+/// What is drawn behind the arena.
+#[derive(Eq, PartialEq, Copy, Clone, Debug)]
+pub enum Background {
+    Starfield,
+    /// Markers every 100 pixels, handy to see the arena coordinates.
+    Grid,
+}
+impl Background {
+    pub fn from_args() -> Background {
+        match arg_value("--background").as_deref() {
+            None | Some("stars") => Background::Starfield,
+            Some("grid") => Background::Grid,
+            Some(background) => {
+                println!("Unknown background {}, expected stars or grid", background);
+                Background::Starfield
+            }
+        }
+    }
+}
+
+pub struct Star {
+    layer: usize,
+    /// Position in the layer tile.
+    position: Vec2,
+}
+
+/// Scrolling of each layer, following the camera moves.
+pub struct Starfield {
+    offsets: Vec<Vec2>,
+    last_view_center: Option<Vec2>,
+}
+
+impl Default for Starfield {
+    fn default() -> Self {
+        Starfield {
+            offsets: vec![Vec2::zero(); STAR_LAYERS.len()],
+            last_view_center: None,
+        }
+    }
+}
+
+pub fn spawn_starfield(
+    mut commands: Commands,
+    background: Res<Background>,
+    seed: Res<GameSeed>,
+    mut materials: ResMut<Assets<ColorMaterial>>,
+) {
+    if *background != Background::Starfield {
+        return;
+    }
+    // Own stream, so the stars don't change the hazards drawn from the same seed.
+    let mut rng = StdRng::seed_from_u64(seed.0 ^ 0x5354_4152);
+    for (layer, spec) in STAR_LAYERS.iter().enumerate() {
+        for _ in 0..spec.count {
+            let brightness = spec.brightness * rng.gen_range(0.6, 1.0);
+            // Slight blue or yellow tint
+            let tint = rng.gen_range(-0.1, 0.1);
+            let color = Color::rgb(brightness - tint, brightness, brightness + tint);
+            let size = spec.size * rng.gen_range(0.7, 1.3);
+            commands
+                .spawn(SpriteComponents {
+                    sprite: Sprite::new(Vec2::new(size, size)),
+                    material: materials.add(color.into()),
+                    transform: Transform::from_translation(Vec3::new(
+                        0.0,
+                        0.0,
+                        STARFIELD_DEPTH + layer as f32,
+                    )),
+                    ..Default::default()
+                })
+                .with(Star {
+                    layer,
+                    position: Vec2::new(
+                        rng.gen_range(0.0, STAR_TILE_SIZE),
+                        rng.gen_range(0.0, STAR_TILE_SIZE),
+                    ),
+                });
+        }
+    }
+}
+
+/// Place each star on its copy closest to the view center.
+/// Layers scroll with the camera moves rather than its position, so the wrap jump is invisible.
+pub fn starfield_system(
+    arena: Res<Arena>,
+    mut starfield: ResMut<Starfield>,
+    mut stars: Query<(&Star, Mut<Transform>)>,
+) {
+    let view_center = arena.view_center;
+    let delta = match starfield.last_view_center {
+        Some(last) => arena.wrapped_delta(last, view_center),
+        None => Vec2::zero(),
+    };
+    starfield.last_view_center = Some(view_center);
+    for (offset, spec) in starfield.offsets.iter_mut().zip(STAR_LAYERS.iter()) {
+        *offset += delta * spec.parallax;
+    }
+    let wrap = |value: f32| value - STAR_TILE_SIZE * (value / STAR_TILE_SIZE).round();
+    let stretch = (arena.view_size.x().max(arena.view_size.y()) / STAR_TILE_SIZE).max(1.0);
+    for (star, mut transform) in stars.iter_mut() {
+        let local = star.position - starfield.offsets[star.layer];
+        let z = transform.translation.z();
+        transform.translation = Vec3::new(
+            view_center.x() + wrap(local.x()) * stretch,
+            view_center.y() + wrap(local.y()) * stretch,
+            z,
+        );
+    }
+}
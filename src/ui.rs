@@ -1,4 +1,7 @@
 use super::*;
+use bevy::ecs::Component;
+/// Tag component for the text describing the cursor selection
+pub struct SelectionText;
 pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
     commands
         // 2d camera
@@ -18,5 +21,70 @@ pub fn setup_ui(mut commands: Commands, asset_server: Res<AssetServer>) {
                 },
             },
             ..Default::default()
+        })
+        .with(SelectionText);
+}
+
+/// Bottom corner of the HUD of a local player, left for the first one and right for the second.
+pub fn hud_position(player: LocalPlayer, bottom: f32) -> Rect<Val> {
+    if player.0 == 0 {
+        Rect {
+            left: Val::Px(10.0),
+            bottom: Val::Px(bottom),
+            ..Default::default()
+        }
+    } else {
+        Rect {
+            right: Val::Px(10.0),
+            bottom: Val::Px(bottom),
+            ..Default::default()
+        }
+    }
+}
+
+/// Spawn a centered panel, with one text per line tagged with the line component.
+/// The root node is tagged with the root component.
+pub fn spawn_panel<R: Component, T: Component>(
+    commands: &mut Commands,
+    materials: &mut Assets<ColorMaterial>,
+    font: Handle<Font>,
+    root: R,
+    lines: Vec<T>,
+) {
+    commands
+        .spawn(NodeComponents {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: Rect {
+                    left: Val::Percent(25.0),
+                    top: Val::Percent(20.0),
+                    ..Default::default()
+                },
+                size: Size::new(Val::Percent(50.0), Val::Percent(60.0)),
+                flex_direction: FlexDirection::ColumnReverse,
+                justify_content: JustifyContent::Center,
+                align_items: AlignItems::Center,
+                ..Default::default()
+            },
+            material: materials.add(Color::rgba(0.0, 0.0, 0.1, 0.8).into()),
+            ..Default::default()
+        })
+        .with(root)
+        .with_children(|parent| {
+            for line in lines {
+                parent
+                    .spawn(TextComponents {
+                        text: Text {
+                            value: "".to_string(),
+                            font: font.clone(),
+                            style: TextStyle {
+                                font_size: 32.0,
+                                color: Color::WHITE,
+                            },
+                        },
+                        ..Default::default()
+                    })
+                    .with(line);
+            }
         });
 }
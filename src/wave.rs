@@ -0,0 +1,42 @@
+use super::*;
+
+pub struct Wave {
+    pub number: u32,
+    /// Enemies of this wave not spawned yet.
+    pub to_spawn: u32,
+    /// Enemies of this wave not destroyed yet, spawned or not.
+    pub remaining: u32,
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        let size = Wave::size(1);
+        Wave {
+            number: 1,
+            to_spawn: size,
+            remaining: size,
+        }
+    }
+    fn size(number: u32) -> u32 {
+        1 + number
+    }
+    pub fn is_cleared(&self) -> bool {
+        self.remaining == 0
+    }
+    pub fn next(&mut self) {
+        self.number += 1;
+        self.to_spawn = Wave::size(self.number);
+        self.remaining = self.to_spawn;
+    }
+    /// To be called each time an enemy of the wave is destroyed.
+    pub fn enemy_destroyed(&mut self) {
+        self.remaining = self.remaining.saturating_sub(1);
+    }
+}
+
+/// Open the shop once all enemies of the wave are destroyed.
+pub fn wave_system(wave: Res<Wave>, network: Res<Network>, mut state: ResMut<GameState>) {
+    if *state == GameState::Playing && wave.is_cleared() && network.simulates_waves() {
+        *state = GameState::Shop;
+    }
+}
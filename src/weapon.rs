@@ -5,6 +5,17 @@ use super::*;
 #[derive(Copy, Clone, Debug)]
 pub enum DamageKind {
     Energy,
+    /// Ramming into enemies or walls
+    Collision,
+}
+impl DamageKind {
+    /// Color of the damage numbers.
+    pub fn color(&self) -> Color {
+        match self {
+            DamageKind::Energy => Color::rgb(0.4, 0.8, 1.0),
+            DamageKind::Collision => Color::rgb(1.0, 0.6, 0.2),
+        }
+    }
 }
 pub struct LifeSpanTimer(Timer);
 #[derive(Copy, Clone, Debug)]
@@ -17,6 +28,7 @@ pub struct DamageDealer {
 pub struct FireWeaponEvent {
     pub ship_entity: Entity,
     pub munition_lifespan: f32,
+    pub damage: u32,
 }
 
 #[derive(Default)]
@@ -29,7 +41,7 @@ pub fn fire_weapon_system(
     fire_weapon_events: Res<Events<FireWeaponEvent>>,
     asset_server: Res<AssetServer>,
     mut materials: ResMut<Assets<ColorMaterial>>,
-    audio: Res<Audio>,
+    mut sound_events: ResMut<Events<SoundEvent>>,
     (mut collide_world, collide_groups): (ResMut<CollisionWorld<f32, Entity>>, Res<CollideGroups>),
     query_transforms: Query<&Transform>,
 ) {
@@ -58,11 +70,13 @@ pub fn fire_weapon_system(
                 .with(DamageDealer {
                     source: fire_weapon_event.ship_entity,
                     kind: DamageKind::Energy,
-                    value: 1,
+                    value: fire_weapon_event.damage,
                 })
                 .with(Movement {
                     speed: (transform.rotation * Vec3::unit_x()).truncate() * 500.0,
                     dampening: 1.0,
+                    angvel: 0.0,
+                    mass: 0.2,
                 })
                 .with(ColliderType::Missile);
             let entity = commands.current_entity().unwrap();
@@ -79,8 +93,10 @@ pub fn fire_weapon_system(
                 entity,
             );
             commands.insert(entity, (collision_object_handle,));
-            let sound = asset_server.load("sfx_laser1.mp3");
-            audio.play(sound);
+            sound_events.send(SoundEvent {
+                sound: Sfx::Laser,
+                position: Some(transform.translation.truncate()),
+            });
         }
     }
 }
@@ -88,8 +104,12 @@ pub fn fire_weapon_system(
 pub fn lifespan_system(
     mut commands: Commands,
     time: Res<Time>,
+    state: Res<GameState>,
     mut query: Query<(Entity, Mut<LifeSpanTimer>)>,
 ) {
+    if *state != GameState::Playing {
+        return;
+    }
     for (entity, mut lifespan_timer) in &mut query.iter_mut() {
         lifespan_timer.0.tick(time.delta_seconds);
         if lifespan_timer.0.finished {